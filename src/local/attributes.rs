@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use super::Pattern;
+
+// value given to an attribute of a path
+#[derive(Clone, PartialEq, Eq)]
+pub enum AttrValue {
+    Set,           // attr
+    Unset,         // -attr
+    Value(String), // attr=value
+}
+
+// one line of an attributes file
+// None removes anything a lower line or file assigned, from !attr
+struct Line {
+    pattern: Pattern,
+    attrs: Vec<(String, Option<AttrValue>)>,
+}
+
+impl Line {
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let pattern = parts.next()?;
+
+        // negative patterns aren't allowed in attribute files
+        if pattern.starts_with('#') || pattern.starts_with('!') {
+            return None;
+        }
+
+        let mut attrs = Vec::new();
+        for attr in parts {
+            if let Some(name) = attr.strip_prefix('-') {
+                attrs.push((name.to_string(), Some(AttrValue::Unset)));
+            } else if let Some(name) = attr.strip_prefix('!') {
+                attrs.push((name.to_string(), None));
+            } else if let Some((name, value)) = attr.split_once('=') {
+                attrs.push((name.to_string(), Some(AttrValue::Value(value.to_string()))));
+            } else if attr == "binary" {
+                // binary is short for -text -diff -merge
+                attrs.push(("binary".to_string(), Some(AttrValue::Set)));
+                for name in ["text", "diff", "merge"] {
+                    attrs.push((name.to_string(), Some(AttrValue::Unset)));
+                }
+            } else {
+                attrs.push((attr.to_string(), Some(AttrValue::Set)));
+            }
+        }
+
+        Some(Self {
+            pattern: Pattern::parse(pattern)?,
+            attrs,
+        })
+    }
+}
+
+fn parse_file(path: &Path) -> Result<Vec<Line>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(Line::parse)
+        .collect())
+}
+
+// attributes assigned to one path
+#[derive(Default)]
+pub struct Attrs(HashMap<String, AttrValue>);
+
+impl Attrs {
+    pub fn get(&self, name: &str) -> Option<&AttrValue> {
+        self.0.get(name)
+    }
+
+    // value of an attribute like diff=<driver>
+    pub fn value(&self, name: &str) -> Option<&str> {
+        match self.0.get(name) {
+            Some(AttrValue::Value(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    // whether line endings may need converting, before looking at the contents
+    // setting eol makes a file text unless text is unset
+    pub fn may_be_text(&self) -> bool {
+        match self.get("text") {
+            Some(AttrValue::Unset) => false,
+            Some(_) => true,
+            None => self.get("eol").is_some(),
+        }
+    }
+
+    // whether a file with these contents has its line endings normalized
+    // text=auto leaves files that look binary alone
+    pub fn is_text(&self, data: &[u8]) -> bool {
+        match self.get("text") {
+            Some(AttrValue::Value(value)) if value == "auto" => !is_binary(data),
+            _ => self.may_be_text(),
+        }
+    }
+
+    // line endings text files have in the working tree
+    pub fn crlf(&self) -> bool {
+        self.value("eol") == Some("crlf")
+    }
+}
+
+// files with a nul byte near the start are treated as binary
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|c| *c == 0)
+}
+
+// convert crlf line endings to lf
+pub fn to_lf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, c) in data.iter().enumerate() {
+        if *c == b'\r' && data.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        out.push(*c);
+    }
+    out
+}
+
+// convert lf line endings to crlf, leaving existing crlf alone
+pub fn to_crlf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, c) in data.iter().enumerate() {
+        if *c == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+        out.push(*c);
+    }
+    out
+}
+
+// attributes of paths from .midattributes files and .mid/info/attributes
+pub struct Attributes {
+    root: PathBuf,
+    // .mid/info/attributes, applied above all .midattributes files
+    info: Vec<Line>,
+    // lines from the .midattributes in each dir, loaded when first needed
+    dirs: HashMap<PathBuf, Vec<Line>>,
+}
+
+impl Attributes {
+    pub fn new(root: &Path, info: &Path) -> Result<Self> {
+        Ok(Self {
+            root: root.to_path_buf(),
+            info: parse_file(info)?,
+            dirs: HashMap::new(),
+        })
+    }
+
+    // attributes of a file relative to the root
+    // deeper files override shallower ones and later lines override earlier ones
+    pub fn get(&mut self, path: &Path) -> Result<Attrs> {
+        let mut dirs = path.ancestors().skip(1).collect::<Vec<_>>();
+        dirs.reverse();
+
+        for dir in &dirs {
+            if !self.dirs.contains_key(*dir) {
+                let lines = parse_file(&self.root.join(dir).join(".midattributes"))?;
+                self.dirs.insert(dir.to_path_buf(), lines);
+            }
+        }
+
+        let mut attrs = HashMap::new();
+
+        let sources = dirs
+            .iter()
+            .map(|dir| (*dir, &self.dirs[*dir]))
+            .chain([(Path::new(""), &self.info)]);
+
+        for (dir, lines) in sources {
+            let rel = path.strip_prefix(dir)?.to_string_lossy();
+
+            for line in lines
+                .iter()
+                .filter(|line| line.pattern.matches(&rel, false))
+            {
+                for (name, value) in &line.attrs {
+                    match value {
+                        Some(value) => attrs.insert(name.clone(), value.clone()),
+                        None => attrs.remove(name),
+                    };
+                }
+            }
+        }
+
+        Ok(Attrs(attrs))
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use hex::ToHex;
+
+use super::{Change, ComHash, DirHash, HeadState, Repo};
+
+// how much of the repo a reset changes
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ResetMode {
+    Soft,  // only the branch
+    Mixed, // the branch and the index
+    Hard,  // the branch, the index and the working tree
+}
+
+impl Repo {
+    // switch to a branch or commit, updating the working tree and the index
+    // local changes to files that are the same in both commits are kept
+    // refuses to overwrite uncommitted changes or untracked files unless forced
+    pub fn checkout(&mut self, target: HeadState, force: bool) -> Result<()> {
+        let root = self.work_tree()?;
+        let new = match &target {
+            HeadState::Branch(name) => match self.branches.get(name) {
+                Some(branch) => branch.head,
+                None => bail!("Branch '{name}' doesn't exist"),
+            },
+            HeadState::Commit(hash) => *hash,
+        };
+
+        let head_tree = self.get_commit(self.get_head()).objs;
+        let staged_tree = self.staged_tree();
+        let new_tree = self.get_commit(new).objs;
+
+        let msg = format!(
+            "checkout: moving from {} to {}",
+            self.head_desc(),
+            match &target {
+                HeadState::Branch(name) => name.clone(),
+                HeadState::Commit(hash) => hash.0.encode_hex::<String>(),
+            }
+        );
+
+        if force {
+            self.overwrite_tree(staged_tree, new_tree)?;
+            self.index = Some(new_tree);
+            self.set_head(target, msg);
+            return Ok(());
+        }
+
+        let status = self.status()?;
+        let changed = self.changed_files(head_tree, new_tree);
+
+        // local changes to files that differ between the commits would be lost
+        let local = status
+            .staged
+            .iter()
+            .chain(&status.unstaged)
+            .map(|(_, path)| path)
+            .collect::<BTreeSet<_>>();
+        let mut conflicts = changed
+            .iter()
+            .filter(|(_, path)| local.contains(path))
+            .map(|(_, path)| path.clone())
+            .collect::<Vec<PathBuf>>();
+
+        // untracked files in the way of files the target adds
+        // an untracked file where the target needs a dir is in the way too
+        let tracked = self.flatten_tree(staged_tree);
+        for (change, path) in &changed {
+            if *change != Change::Added {
+                continue;
+            }
+
+            if !tracked.contains_key(path) && root.join(path).symlink_metadata().is_ok() {
+                conflicts.push(path.clone());
+            }
+            for dir in path
+                .ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_os_str().is_empty())
+            {
+                let in_the_way = root
+                    .join(dir)
+                    .symlink_metadata()
+                    .is_ok_and(|meta| !meta.is_dir());
+                if in_the_way && !tracked.contains_key(dir) {
+                    conflicts.push(dir.to_path_buf());
+                }
+            }
+        }
+
+        if !conflicts.is_empty() {
+            conflicts.sort();
+            conflicts.dedup();
+            let list = conflicts
+                .iter()
+                .map(|path| format!("\t{}", path.display()))
+                .collect::<Vec<_>>()
+                .join("\n");
+            bail!("Your local changes to these files would be overwritten by checkout:\n{list}");
+        }
+
+        self.write_tree(head_tree, new_tree, &root)?;
+
+        // staged changes to files the commits agree on stay staged
+        let edits = status
+            .staged
+            .iter()
+            .map(|(_, path)| (path.clone(), self.tree_lookup(staged_tree, path)))
+            .collect::<Vec<_>>();
+        self.index = Some(self.edit_tree(new_tree, edits)?);
+
+        self.set_head(target, msg);
+        Ok(())
+    }
+
+    // move the current branch, or HEAD if it is detached, to another commit
+    // mixed also resets the index and hard the working tree
+    pub fn reset(&mut self, new: ComHash, mode: ResetMode, rev: &str) -> Result<()> {
+        let new_tree = self.get_commit(new).objs;
+
+        if mode == ResetMode::Hard {
+            let staged_tree = self.staged_tree();
+            self.overwrite_tree(staged_tree, new_tree)?;
+        }
+        if mode == ResetMode::Soft {
+            // no index means the same as HEAD, pin it so it doesn't follow HEAD
+            self.index = Some(self.staged_tree());
+        } else {
+            self.index = Some(new_tree);
+        }
+
+        self.move_head(new, format!("reset: moving to {rev}"));
+        Ok(())
+    }
+
+    // write every file of a tree to the working tree, throwing away local changes
+    // files only tracked in the old tree are deleted, untracked files are left alone
+    fn overwrite_tree(&mut self, old: DirHash, new: DirHash) -> Result<()> {
+        let root = self.work_tree()?;
+        let old = self.flatten_tree(old);
+        let files = self.flatten_tree(new);
+
+        for path in old.keys().filter(|path| !files.contains_key(*path)) {
+            self.delete_file(path)?;
+        }
+        for (path, (hash, permissions)) in &files {
+            let full = root.join(path);
+            if full.is_dir() {
+                fs::remove_dir_all(&full)?;
+            }
+            self.write_file(*hash, *permissions, &full)?;
+        }
+
+        Ok(())
+    }
+
+    // name of what HEAD points at for messages
+    fn head_desc(&self) -> String {
+        match &self.head {
+            HeadState::Branch(name) => name.clone(),
+            HeadState::Commit(hash) => hash.0.encode_hex::<String>(),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+use super::{ComHash, DirHash, Object, Repo};
+
+// what to do when a tree gets names that only differ by case or unicode normalization
+// such names overwrite each other when checked out on case insensitive or normalizing filesystems
+#[derive(Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionCheck {
+    Allow,
+    #[default]
+    Warn,
+    Refuse,
+}
+
+// names in one dir that would be the same file on some filesystems
+pub struct Collision {
+    pub dir: PathBuf, // relative to the root
+    pub names: Vec<OsString>,
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let paths = self
+            .names
+            .iter()
+            .map(|name| format!("'{}'", self.dir.join(name).display()))
+            .collect::<Vec<_>>();
+        write!(f, "{}", paths.join(", "))
+    }
+}
+
+// name as a case insensitive filesystem in NFC would see it
+// names that aren't unicode can only collide with themselves
+fn fold_name(name: &OsStr) -> Option<String> {
+    Some(name.to_str()?.nfc().collect::<String>().to_lowercase())
+}
+
+// groups of names in a dir that fold to the same name
+fn colliding_names<'a>(names: impl Iterator<Item = &'a OsString>) -> Vec<Vec<OsString>> {
+    let mut folded: HashMap<String, Vec<OsString>> = HashMap::new();
+    for name in names {
+        if let Some(key) = fold_name(name) {
+            folded.entry(key).or_default().push(name.clone());
+        }
+    }
+
+    let mut groups = folded
+        .into_values()
+        .filter(|names| names.len() > 1)
+        .map(|mut names| {
+            names.sort();
+            names
+        })
+        .collect::<Vec<_>>();
+    groups.sort();
+    groups
+}
+
+impl Repo {
+    // collisions in new that old doesn't have
+    // only dirs that differ are looked at, with no old tree every dir is checked
+    pub fn collisions(&self, old: Option<DirHash>, new: DirHash) -> Vec<Collision> {
+        let mut out = Vec::new();
+        self.find_collisions(old, new, Path::new(""), &mut HashSet::new(), &mut out);
+        out
+    }
+
+    // seen holds dirs that were already checked so shared subtrees are only looked at once
+    fn find_collisions(
+        &self,
+        old: Option<DirHash>,
+        new: DirHash,
+        path: &Path,
+        seen: &mut HashSet<DirHash>,
+        out: &mut Vec<Collision>,
+    ) {
+        if old == Some(new) || !seen.insert(new) {
+            return;
+        }
+
+        // collisions the dir already had aren't new
+        let old_dir = old.map(|old| &self.get_dir(old).objs);
+        let existing = old_dir.map_or_else(Vec::new, |objs| colliding_names(objs.keys()));
+
+        let objs = &self.get_dir(new).objs;
+        for names in colliding_names(objs.keys()) {
+            if existing.contains(&names) {
+                continue;
+            }
+            out.push(Collision {
+                dir: path.to_path_buf(),
+                names,
+            });
+        }
+
+        for (name, obj) in objs {
+            let Object::Dir(hash) = obj else {
+                continue;
+            };
+            let old = old_dir
+                .and_then(|objs| objs.get(name))
+                .and_then(Object::get_dir);
+            self.find_collisions(old, *hash, &path.join(name), seen, out);
+        }
+    }
+
+    // refuse a change from old to new if it adds collisions and that is configured
+    pub(super) fn check_collisions(&self, old: DirHash, new: DirHash) -> Result<()> {
+        if self.config.name_collisions != CollisionCheck::Refuse {
+            return Ok(());
+        }
+
+        let collisions = self.collisions(Some(old), new);
+        if let Some(collision) = collisions.first() {
+            bail!(
+                "Names that only differ by case or unicode normalization: {collision}{}",
+                match collisions.len() {
+                    1 => String::new(),
+                    n => format!(" and {} more", n - 1),
+                }
+            );
+        }
+        Ok(())
+    }
+
+    // look for collisions in every commit reachable from branches, tags, HEAD and the stash
+    // each one is reported with the first commit it was found in
+    pub fn fsck(&self) -> Vec<(ComHash, Collision)> {
+        let mut starts = vec![self.get_head()];
+        starts.extend(self.branches.values().map(|branch| branch.head));
+        starts.extend(self.tags.values().map(|tag| self.peel_tag(*tag)));
+        starts.extend(self.stash.iter().map(|entry| entry.commit));
+
+        let mut commits = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut out = Vec::new();
+
+        for start in starts {
+            let mut commit = start;
+            while commit.0 != [0; 20] && commits.insert(commit) {
+                let mut found = Vec::new();
+                let tree = self.get_commit(commit).objs;
+                self.find_collisions(None, tree, Path::new(""), &mut seen, &mut found);
+                out.extend(found.into_iter().map(|collision| (commit, collision)));
+
+                commit = self.get_commit(commit).prev;
+            }
+        }
+
+        out
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 
-use super::{DirHash, ObjectState};
+use super::{push_field, DirHash, ObjectState, Signature};
 
 #[derive(Hash, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -12,25 +12,55 @@ pub struct Commit {
     pub msg: String,
     pub prev: ComHash,
     pub objs: DirHash,
+    // missing from commits made before they were recorded, time is 0 for those
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub time: u64, // seconds since the unix epoch
+    // not part of the hash, signs the payload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
     #[serde(skip)]
     pub state: ObjectState,
 }
 
 impl Commit {
-    pub fn new(msg: String, prev: ComHash, objs: DirHash) -> Self {
+    pub fn new(msg: String, prev: ComHash, objs: DirHash, author: String, time: u64) -> Self {
         Self {
             msg,
             prev,
             objs,
+            author,
+            time,
+            signature: None,
             state: ObjectState::New,
         }
     }
 
+    // bytes covered by the signature
+    pub fn payload(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        push_field(&mut payload, b"commit");
+        push_field(&mut payload, &self.prev.0);
+        push_field(&mut payload, &self.objs.0);
+        push_field(&mut payload, self.msg.as_bytes());
+        if self.time != 0 {
+            push_field(&mut payload, self.author.as_bytes());
+            push_field(&mut payload, &self.time.to_be_bytes());
+        }
+        payload
+    }
+
     // hash object
+    // commits without an author and time hash the same as before they were recorded
     pub fn hash(&self) -> ComHash {
         let mut hasher = Sha1::new();
         hasher.update(self.prev.0);
         hasher.update(self.objs.0);
+        if self.time != 0 {
+            hasher.update(self.author.as_bytes());
+            hasher.update(self.time.to_be_bytes());
+        }
         ComHash(hasher.finalize()[..].try_into().unwrap())
     }
 }
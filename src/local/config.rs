@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::{CollisionCheck, FilterDriver};
+
+// local settings of the repository
+// stored in .mid/config.json, every field is optional
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub user: Option<String>, // name recorded on tags and commits
+
+    pub signing_key: Option<PathBuf>, // file with the hex ed25519 secret key
+    pub sign_commits: bool,           // sign every commit and annotated tag
+    pub trusted_keys: Vec<String>,    // hex public keys accepted when verifying, any if empty
+
+    pub excludes_file: Option<PathBuf>, // global ignore file, ~/.config/mid/ignore by default
+
+    pub filters: HashMap<String, FilterDriver>, // content filters by name, see .midattributes
+
+    pub threads: usize, // threads used to hash and write files, one per core if 0
+
+    // names differing only by case or unicode normalization: allow, warn or refuse
+    pub name_collisions: CollisionCheck,
+}
+
+impl Config {
+    // name of the current user
+    // falls back to the environment if it isn't configured
+    pub fn identity(&self) -> String {
+        self.user
+            .clone()
+            .or_else(|| env::var("MID_USER").ok())
+            .or_else(|| env::var("USER").ok())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    // ignore file shared by all repositories
+    pub fn excludes_file(&self) -> Option<PathBuf> {
+        self.excludes_file.clone().or_else(|| {
+            env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+                .map(|config| config.join("mid/ignore"))
+        })
+    }
+}
@@ -0,0 +1,524 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use hex::ToHex;
+
+use super::patch::{split_lines, Hunk, HunkLine};
+use super::{is_binary, AttrValue, Change, DirHash, FileHash, Object, Repo};
+
+// an entry that differs between two trees
+pub struct TreeChange {
+    pub path: PathBuf,       // relative to the root
+    pub old: Option<Object>, // None when the entry was added
+    pub new: Option<Object>, // None when the entry was deleted
+}
+
+impl TreeChange {
+    pub fn change(&self) -> Change {
+        match (self.old, self.new) {
+            (None, _) => Change::Added,
+            (_, None) => Change::Deleted,
+            (Some(Object::File(..)), Some(Object::File(..))) => Change::Modified,
+            _ => Change::TypeChanged,
+        }
+    }
+}
+
+impl Repo {
+    // entries that differ between two trees, in path order
+    // files are listed with their full path, dirs only when they replace a file or are replaced by one
+    // the files in such a dir follow it as added or deleted
+    // subtrees with the same hash on both sides are skipped without being loaded
+    pub fn diff_trees(&self, old: DirHash, new: DirHash) -> Vec<TreeChange> {
+        let mut changes = Vec::new();
+        self.diff_dirs(Some(old), Some(new), Path::new(""), &mut changes);
+        changes
+    }
+
+    // None stands for a dir that doesn't exist on that side
+    fn diff_dirs(
+        &self,
+        old: Option<DirHash>,
+        new: Option<DirHash>,
+        path: &Path,
+        changes: &mut Vec<TreeChange>,
+    ) {
+        if old == new {
+            return;
+        }
+
+        let empty = HashMap::new();
+        let old_objs = old.map_or(&empty, |hash| &self.get_dir(hash).objs);
+        let new_objs = new.map_or(&empty, |hash| &self.get_dir(hash).objs);
+
+        let names = old_objs
+            .keys()
+            .chain(new_objs.keys())
+            .collect::<BTreeSet<_>>();
+
+        for name in names {
+            let old = old_objs.get(name).copied();
+            let new = new_objs.get(name).copied();
+            if old == new {
+                continue;
+            }
+
+            let path = path.join(name);
+            let old_dir = old.and_then(|obj| obj.get_dir());
+            let new_dir = new.and_then(|obj| obj.get_dir());
+
+            match (old_dir, new_dir) {
+                (None, None) => changes.push(TreeChange { path, old, new }),
+                (Some(_), Some(_)) => self.diff_dirs(old_dir, new_dir, &path, changes),
+                _ => {
+                    if old.is_some() && new.is_some() {
+                        changes.push(TreeChange {
+                            path: path.clone(),
+                            old,
+                            new,
+                        });
+                    }
+                    self.diff_dirs(old_dir, new_dir, &path, changes);
+                }
+            }
+        }
+    }
+}
+
+// furthest point reached on each diagonal k = x - y of the edit graph
+struct Diagonals {
+    offset: isize,
+    v: Vec<usize>,
+}
+
+impl Diagonals {
+    fn new(max: usize) -> Self {
+        Self {
+            offset: max as isize + 1,
+            v: vec![0; 2 * max + 3],
+        }
+    }
+
+    fn get(&self, k: isize) -> usize {
+        self.v[(k + self.offset) as usize]
+    }
+
+    fn set(&mut self, k: isize, x: usize) {
+        self.v[(k + self.offset) as usize] = x;
+    }
+}
+
+// search for the longest common subsequence of two lists of line numbers
+struct Script {
+    old: Vec<usize>,
+    new: Vec<usize>,
+    forward: Diagonals,
+    backward: Diagonals,
+    matches: Vec<(usize, usize)>, // positions in old and new of the lines that are kept
+}
+
+impl Script {
+    // start of the middle snake of the shortest path from old[a] new[b] to old[a_end] new[b_end]
+    // searches from both ends at once so only linear space is needed
+    fn middle_snake(&mut self, a: usize, a_end: usize, b: usize, b_end: usize) -> (usize, usize) {
+        let (n, m) = (a_end - a, b_end - b);
+        let delta = n as isize - m as isize;
+        let odd = delta & 1 == 1;
+        self.forward.set(1, 0);
+        self.backward.set(1, 0);
+
+        for d in 0..=(n + m).div_ceil(2) as isize {
+            // forward from the start, x counts lines of old from a
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x =
+                    if k == -d || (k != d && self.forward.get(k - 1) < self.forward.get(k + 1)) {
+                        self.forward.get(k + 1)
+                    } else {
+                        self.forward.get(k - 1) + 1
+                    };
+                let (x0, y0) = (x, (x as isize - k) as usize);
+                let mut y = y0;
+                while x < n && y < m && self.old[a + x] == self.new[b + y] {
+                    x += 1;
+                    y += 1;
+                }
+                self.forward.set(k, x);
+
+                if odd && (k - delta).abs() < d && x + self.backward.get(-(k - delta)) >= n {
+                    return (a + x0, b + y0);
+                }
+            }
+
+            // backward from the end, x counts lines of old back from a_end
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x =
+                    if k == -d || (k != d && self.backward.get(k - 1) < self.backward.get(k + 1)) {
+                        self.backward.get(k + 1)
+                    } else {
+                        self.backward.get(k - 1) + 1
+                    };
+                let mut y = (x as isize - k) as usize;
+                while x < n && y < m && self.old[a_end - x - 1] == self.new[b_end - y - 1] {
+                    x += 1;
+                    y += 1;
+                }
+                self.backward.set(k, x);
+
+                if !odd && (k - delta).abs() <= d && x + self.forward.get(-(k - delta)) >= n {
+                    return (a_end - x, b_end - y);
+                }
+            }
+        }
+
+        unreachable!("paths from both ends always meet")
+    }
+
+    // lines kept when turning old[a..a_end] into new[b..b_end]
+    fn diff(&mut self, mut a: usize, mut a_end: usize, mut b: usize, mut b_end: usize) {
+        while a < a_end && b < b_end && self.old[a] == self.new[b] {
+            self.matches.push((a, b));
+            a += 1;
+            b += 1;
+        }
+        let mut suffix = 0;
+        while a < a_end && b < b_end && self.old[a_end - 1] == self.new[b_end - 1] {
+            a_end -= 1;
+            b_end -= 1;
+            suffix += 1;
+        }
+
+        // everything left on one side is added or removed
+        if a < a_end && b < b_end {
+            let (x, y) = self.middle_snake(a, a_end, b, b_end);
+            self.diff(a, x, b, y);
+            self.diff(x, a_end, y, b_end);
+        }
+
+        for i in 0..suffix {
+            self.matches.push((a_end + i, b_end + i));
+        }
+    }
+}
+
+// shortest edit script between two lists of lines with Myers' O(ND) algorithm
+// the lines are returned in order as kept, removed or added
+fn myers(old: &[&[u8]], new: &[&[u8]]) -> Vec<HunkLine> {
+    let mut ids: HashMap<&[u8], usize> = HashMap::new();
+    let old_ids = old
+        .iter()
+        .map(|line| {
+            let next = ids.len();
+            *ids.entry(line).or_insert(next)
+        })
+        .collect::<Vec<_>>();
+    let new_ids = new
+        .iter()
+        .map(|line| {
+            let next = ids.len();
+            *ids.entry(line).or_insert(next)
+        })
+        .collect::<Vec<_>>();
+
+    // lines only in one of the files can't be kept, leaving them out speeds up very different files
+    let in_old = old_ids.iter().collect::<HashSet<_>>();
+    let in_new = new_ids.iter().collect::<HashSet<_>>();
+    let old_keep = (0..old.len())
+        .filter(|i| in_new.contains(&old_ids[*i]))
+        .collect::<Vec<_>>();
+    let new_keep = (0..new.len())
+        .filter(|i| in_old.contains(&new_ids[*i]))
+        .collect::<Vec<_>>();
+
+    let max = old_keep.len() + new_keep.len();
+    let mut script = Script {
+        old: old_keep.iter().map(|i| old_ids[*i]).collect(),
+        new: new_keep.iter().map(|i| new_ids[*i]).collect(),
+        forward: Diagonals::new(max),
+        backward: Diagonals::new(max),
+        matches: Vec::new(),
+    };
+    script.diff(0, old_keep.len(), 0, new_keep.len());
+
+    // removed and added lines go between the kept ones
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let kept = script
+        .matches
+        .iter()
+        .map(|(a, b)| (old_keep[*a], new_keep[*b]))
+        .chain([(old.len(), new.len())]);
+    for (a, b) in kept {
+        lines.extend(
+            old[i..a]
+                .iter()
+                .map(|line| HunkLine::Removed(line.to_vec())),
+        );
+        lines.extend(new[j..b].iter().map(|line| HunkLine::Added(line.to_vec())));
+        if a < old.len() {
+            lines.push(HunkLine::Context(old[a].to_vec()));
+        }
+        (i, j) = (a + 1, b + 1);
+    }
+    lines
+}
+
+// hunks changing old into new with up to context unchanged lines around each change
+// hunks closer together than that are joined
+pub fn diff_lines(old: &[u8], new: &[u8], context: usize) -> Vec<Hunk> {
+    let lines = myers(&split_lines(old), &split_lines(new));
+
+    // lines of each file before each line of the script
+    let mut before = vec![(0, 0)];
+    for line in &lines {
+        let (old, new) = *before.last().unwrap();
+        before.push(match line {
+            HunkLine::Context(_) => (old + 1, new + 1),
+            HunkLine::Removed(_) => (old + 1, new),
+            HunkLine::Added(_) => (old, new + 1),
+        });
+    }
+
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, HunkLine::Context(_)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < changed.len() {
+        let start = changed[i].saturating_sub(context);
+        while i + 1 < changed.len() && changed[i + 1] - changed[i] - 1 <= 2 * context {
+            i += 1;
+        }
+        let end = (changed[i] + context + 1).min(lines.len());
+        i += 1;
+
+        let old_len = before[end].0 - before[start].0;
+        let new_len = before[end].1 - before[start].1;
+        // an empty side starts at the line before it like in the @@ header
+        hunks.push(Hunk {
+            old_start: before[start].0 + usize::from(old_len > 0),
+            old_len,
+            new_start: before[start].1 + usize::from(new_len > 0),
+            new_len,
+            lines: lines[start..end].to_vec(),
+        });
+    }
+
+    hunks
+}
+
+// write hunks in the unified format
+pub fn write_hunks(out: &mut impl Write, hunks: &[Hunk]) -> Result<()> {
+    // lengths of 1 are left out of the header
+    let range = |start: usize, len: usize| match len {
+        1 => start.to_string(),
+        _ => format!("{start},{len}"),
+    };
+
+    for hunk in hunks {
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            range(hunk.old_start, hunk.old_len),
+            range(hunk.new_start, hunk.new_len)
+        )?;
+
+        for line in &hunk.lines {
+            let (kind, text) = match line {
+                HunkLine::Context(text) => (b' ', text),
+                HunkLine::Removed(text) => (b'-', text),
+                HunkLine::Added(text) => (b'+', text),
+            };
+            out.write_all(&[kind])?;
+            out.write_all(text)?;
+            if text.last() != Some(&b'\n') {
+                out.write_all(b"\n\\ No newline at end of file\n")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Repo {
+    // write the unified diff of one entry from diff_trees
+    // a dir on either side is left to the entries for the files in it
+    // files with -diff or that look binary are only reported as different
+    pub fn write_file_diff(
+        &self,
+        out: &mut impl Write,
+        entry: &TreeChange,
+        context: usize,
+    ) -> Result<()> {
+        let file = |obj: Option<Object>| match obj {
+            Some(Object::File(hash, permissions)) => Some((hash, 0o100000 | permissions)),
+            _ => None,
+        };
+        let (old_mode, new_mode) = (file(entry.old), file(entry.new));
+        let old = old_mode.map(|(hash, _)| hash);
+        let new = new_mode.map(|(hash, _)| hash);
+        if old.is_none() && new.is_none() {
+            return Ok(());
+        }
+
+        let path = entry.path.display();
+        writeln!(out, "diff --git a/{path} b/{path}")?;
+
+        match (old_mode, new_mode) {
+            (None, Some((_, mode))) => writeln!(out, "new file mode {mode:o}")?,
+            (Some((_, mode)), None) => writeln!(out, "deleted file mode {mode:o}")?,
+            (Some((_, old)), Some((_, new))) if old != new => {
+                writeln!(out, "old mode {old:o}")?;
+                writeln!(out, "new mode {new:o}")?;
+            }
+            _ => {}
+        }
+        // only the mode changed
+        if old == new {
+            return Ok(());
+        }
+
+        let short = |hash: Option<FileHash>| match hash {
+            Some(hash) => hash.0.encode_hex::<String>()[..7].to_string(),
+            None => "0".repeat(7),
+        };
+        writeln!(out, "index {}..{}", short(old), short(new))?;
+
+        let read = |hash: Option<FileHash>| match hash {
+            Some(hash) => self.read_file(hash),
+            None => Ok(Vec::new()),
+        };
+        let old_data = read(old)?;
+        let new_data = read(new)?;
+
+        let old_name = match old {
+            Some(_) => format!("a/{path}"),
+            None => "/dev/null".to_string(),
+        };
+        let new_name = match new {
+            Some(_) => format!("b/{path}"),
+            None => "/dev/null".to_string(),
+        };
+
+        let attrs = self.attributes(&entry.path)?;
+        if attrs.get("diff") == Some(&AttrValue::Unset)
+            || is_binary(&old_data)
+            || is_binary(&new_data)
+        {
+            writeln!(out, "Binary files {old_name} and {new_name} differ")?;
+            return Ok(());
+        }
+
+        let hunks = diff_lines(&old_data, &new_data, context);
+        if !hunks.is_empty() {
+            writeln!(out, "--- {old_name}")?;
+            writeln!(out, "+++ {new_name}")?;
+            write_hunks(out, &hunks)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::patch::apply_hunks;
+    use super::*;
+
+    // diff_lines as it is printed
+    fn unified(old: &str, new: &str, context: usize) -> String {
+        let mut out = Vec::new();
+        write_hunks(
+            &mut out,
+            &diff_lines(old.as_bytes(), new.as_bytes(), context),
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn empty_files() {
+        assert_eq!(unified("", "", 3), "");
+        assert_eq!(unified("", "a\n", 3), "@@ -0,0 +1 @@\n+a\n");
+        assert_eq!(unified("a\n", "", 3), "@@ -1 +0,0 @@\n-a\n");
+    }
+
+    #[test]
+    fn missing_newline() {
+        assert_eq!(
+            unified("a\nb", "a\nb\n", 3),
+            "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+b\n"
+        );
+        assert_eq!(
+            unified("a\nb\n", "a\nb", 3),
+            "@@ -1,2 +1,2 @@\n a\n-b\n+b\n\\ No newline at end of file\n"
+        );
+        assert_eq!(
+            unified("a", "b", 3),
+            "@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+b\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn no_context() {
+        assert_eq!(
+            unified("a\nb\nc\n", "a\nB\nc\n", 0),
+            "@@ -2 +2 @@\n-b\n+B\n"
+        );
+        assert_eq!(unified("a\nc\n", "a\nb\nc\n", 0), "@@ -1,0 +2 @@\n+b\n");
+        assert_eq!(unified("a\nb\nc\n", "a\nc\n", 0), "@@ -2 +1,0 @@\n-b\n");
+        assert_eq!(
+            unified("a\nb\nc\n", "A\nb\nC\n", 0),
+            "@@ -1 +1 @@\n-a\n+A\n@@ -3 +3 @@\n-c\n+C\n"
+        );
+    }
+
+    #[test]
+    fn hunks_merge_at_twice_the_context() {
+        let old = "a\nb\nc\nd\ne\nf\ng\n";
+
+        // two unchanged lines between the changes, one hunk
+        let hunks = diff_lines(old.as_bytes(), b"A\nb\nc\nD\ne\nf\ng\n", 1);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!((hunks[0].old_start, hunks[0].old_len), (1, 5));
+
+        // three unchanged lines between the changes, two hunks
+        let hunks = diff_lines(old.as_bytes(), b"A\nb\nc\nd\nE\nf\ng\n", 1);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!((hunks[0].old_start, hunks[0].old_len), (1, 2));
+        assert_eq!((hunks[1].old_start, hunks[1].old_len), (4, 3));
+    }
+
+    #[test]
+    fn hunks_apply_back() {
+        let files = [
+            "",
+            "a\n",
+            "a",
+            "a\nb\nc\nd\ne\nf\ng\nh\n",
+            "a\nx\nc\nd\ne\nf\ny\nh",
+            "b\nc\na\nd\nf\ne\ng\nh\n",
+            "a\na\na\nb\na\na\n",
+            "\n\n\n",
+        ];
+
+        for old in files {
+            for new in files {
+                for context in [0, 1, 3] {
+                    let hunks = diff_lines(old.as_bytes(), new.as_bytes(), context);
+                    let applied = apply_hunks(old.as_bytes(), &hunks, Path::new("f")).unwrap();
+                    assert_eq!(
+                        applied,
+                        new.as_bytes(),
+                        "{old:?} to {new:?} with -U{context}"
+                    );
+                }
+            }
+        }
+    }
+}
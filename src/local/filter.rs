@@ -0,0 +1,127 @@
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{hash_data, hash_file, to_lf, Attrs, FileHash};
+
+// external commands transforming contents between the working tree and the store
+// selected for a path with the filter=<name> attribute
+// %f in a command is replaced with the path of the file relative to the root
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterDriver {
+    pub clean: Option<String>, // working tree to store, runs when files are hashed
+    pub smudge: Option<String>, // store to working tree, runs on checkout
+    pub required: bool,        // fail instead of passing contents through unchanged
+}
+
+// quote a string for sh
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+// run a filter command in dir, streaming input to its stdin and collecting its stdout
+pub fn run_filter(
+    command: &str,
+    dir: &Path,
+    path: &Path,
+    input: impl Read + Send,
+) -> Result<Vec<u8>> {
+    let command = command.replace("%f", &quote(&path.to_string_lossy()));
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run filter '{command}'"))?;
+
+    let mut stdin = child.stdin.take().context("Filter has no stdin")?;
+    let mut stdout = child.stdout.take().context("Filter has no stdout")?;
+    let mut output = Vec::new();
+
+    // write on another thread so a filter that writes before it reads everything can't deadlock
+    thread::scope(|scope| -> Result<()> {
+        let writer = scope.spawn(move || -> io::Result<()> {
+            let mut input = input;
+            match io::copy(&mut input, &mut stdin) {
+                // filters don't have to read all of their input
+                Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(()),
+                other => other.map(|_| ()),
+            }?;
+            stdin.flush()
+        });
+
+        stdout.read_to_end(&mut output)?;
+        match writer.join() {
+            Ok(result) => Ok(result?),
+            Err(_) => bail!("Failed to write to filter '{command}'"),
+        }
+    })?;
+
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("Filter '{command}' failed with {status}");
+    }
+
+    Ok(output)
+}
+
+// conversion of one file from the working tree to how it is stored
+// worked out up front so files can be converted and hashed on any thread
+pub struct CleanJob {
+    path: PathBuf,
+    rel: PathBuf,
+    attrs: Attrs,
+    clean: Option<(String, bool)>, // clean command and whether it is required
+}
+
+impl CleanJob {
+    pub fn new(path: &Path, rel: &Path, attrs: Attrs, clean: Option<(String, bool)>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            rel: rel.to_path_buf(),
+            attrs,
+            clean,
+        }
+    }
+
+    // hash of the file as it is stored
+    // the converted contents if they differ from the file, None if it is stored as is
+    // the clean filter runs first, then line endings are normalized
+    pub fn run(self, root: &Path) -> Result<(FileHash, Option<Vec<u8>>)> {
+        if self.clean.is_none() && !self.attrs.may_be_text() {
+            return Ok((hash_file(&self.path)?, None));
+        }
+
+        let contents = fs::read(&self.path)?;
+        let mut data = match &self.clean {
+            Some((command, required)) => {
+                match run_filter(command, root, &self.rel, File::open(&self.path)?) {
+                    Ok(data) => Some(data),
+                    Err(err) if *required => return Err(err),
+                    Err(_) => None,
+                }
+            }
+            None => None,
+        };
+
+        let cleaned = data.as_deref().unwrap_or(&contents);
+        if self.attrs.is_text(cleaned) {
+            data = Some(to_lf(cleaned));
+        }
+
+        // files that didn't need converting are stored straight from the working tree
+        match data {
+            Some(data) if data != contents => Ok((hash_data(&data), Some(data))),
+            _ => Ok((hash_data(&contents), None)),
+        }
+    }
+}
@@ -1,10 +1,11 @@
-use std::ffi::OsString;
-use std::fs::File;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, Metadata, Permissions};
 use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::{collections::HashMap, io};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 
@@ -19,32 +20,64 @@ pub struct DirHash(#[serde(with = "hex::serde")] pub [u8; 20]);
 pub struct FileHash(#[serde(with = "hex::serde")] pub [u8; 20]);
 
 // type of objects in the file tree
-#[derive(Copy, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
+// files carry their mode, 0o755 for executables and 0o644 otherwise
+// the mode belongs to the entry so the same contents can be stored under both
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "StoredObject", into = "StoredObject")]
 pub enum Object {
+    File(FileHash, u32),
+    Dir(DirHash),
+}
+
+// how objects are written in dir objects
+// entries from before modes were recorded are plain files
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum StoredObject {
     File(FileHash),
+    Executable(FileHash),
     Dir(DirHash),
 }
 
+impl From<StoredObject> for Object {
+    fn from(obj: StoredObject) -> Self {
+        match obj {
+            StoredObject::File(hash) => Object::File(hash, 0o644),
+            StoredObject::Executable(hash) => Object::File(hash, 0o755),
+            StoredObject::Dir(hash) => Object::Dir(hash),
+        }
+    }
+}
+
+impl From<Object> for StoredObject {
+    fn from(obj: Object) -> Self {
+        match obj {
+            Object::File(hash, 0o755) => StoredObject::Executable(hash),
+            Object::File(hash, _) => StoredObject::File(hash),
+            Object::Dir(hash) => StoredObject::Dir(hash),
+        }
+    }
+}
+
 impl Object {
     pub fn hash(&self) -> [u8; 20] {
         match self {
-            Object::File(hash) => hash.0,
+            Object::File(hash, _) => hash.0,
             Object::Dir(hash) => hash.0,
         }
     }
 
     pub fn get_dir(&self) -> Option<DirHash> {
         match self {
-            Object::File(hash) => None,
+            Object::File(..) => None,
             Object::Dir(hash) => Some(*hash),
         }
     }
 
     pub fn get_file(&self) -> Option<FileHash> {
         match self {
-            Object::File(hash) => Some(*hash),
-            Object::Dir(hash) => None,
+            Object::File(hash, _) => Some(*hash),
+            Object::Dir(_) => None,
         }
     }
 }
@@ -59,11 +92,13 @@ pub enum FileState {
     // new object that needs to be stored
     // field contains path that the file needs to be stored from
     New(PathBuf),
+    // new object that was created in memory rather than read from the working tree
+    Data(Vec<u8>),
 }
 
+// the mode of a file is kept by the dir entries pointing at it
 #[derive(Serialize, Deserialize)]
 pub struct FileObject {
-    pub permissions: u32,
     #[serde(skip)]
     pub state: FileState,
 }
@@ -71,7 +106,6 @@ pub struct FileObject {
 impl FileObject {
     pub fn new(path: impl AsRef<Path>) -> Self {
         Self {
-            permissions: 0,
             state: FileState::New(path.as_ref().to_path_buf()),
         }
     }
@@ -87,8 +121,40 @@ pub fn hash_file(path: impl AsRef<Path>) -> Result<FileHash> {
     Ok(FileHash(hasher.finalize()[..].try_into().unwrap()))
 }
 
-pub fn get_permissions(file: File) {
-    todo!()
+// find hash of file contents in memory
+pub fn hash_data(data: &[u8]) -> FileHash {
+    FileHash(Sha1::digest(data)[..].try_into().unwrap())
+}
+
+// mode of a file in the working tree
+pub fn get_permissions(meta: &Metadata) -> u32 {
+    mode_permissions(meta.permissions().mode())
+}
+
+// mode recorded for a file with a st_mode
+// only whether it is executable is tracked
+pub fn mode_permissions(mode: u32) -> u32 {
+    if mode & 0o111 != 0 {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+// apply a stored mode to a file in the working tree
+pub fn set_permissions(path: impl AsRef<Path>, permissions: u32) -> Result<()> {
+    fs::set_permissions(path, Permissions::from_mode(permissions))?;
+    Ok(())
+}
+
+// check that a name can be an entry of a dir
+// names like .. or with a / in them would reach outside the dir when written
+pub fn check_entry_name(name: &OsStr) -> Result<()> {
+    let bytes = name.as_bytes();
+    if matches!(bytes, b"" | b"." | b"..") || bytes.contains(&b'/') || bytes.contains(&0) {
+        bail!("'{}' isn't a valid file name", name.to_string_lossy());
+    }
+    Ok(())
 }
 
 // module for serializing and deserializing the dir objects
@@ -135,7 +201,7 @@ pub struct DirObject {
 impl DirObject {
     pub fn new(filehash: FileHash) -> Self {
         let mut objs = HashMap::new();
-        objs.insert("default".to_string().into(), Object::File(filehash));
+        objs.insert("default".to_string().into(), Object::File(filehash, 0o644));
 
         Self {
             objs,
@@ -147,9 +213,20 @@ impl DirObject {
     pub fn hash(&self) -> DirHash {
         let mut hasher = Sha1::new();
 
-        for (key, value) in &self.objs {
+        // sort entries so the hash doesn't depend on hashmap order
+        let mut entries = self.objs.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (key, value) in entries {
             hasher.update(key.as_bytes());
             hasher.update(value.hash());
+
+            // plain files hash the same as before modes were recorded
+            if let Object::File(_, permissions) = value {
+                if *permissions != 0o644 {
+                    hasher.update(permissions.to_be_bytes());
+                }
+            }
         }
 
         DirHash(hasher.finalize()[..].try_into().unwrap())
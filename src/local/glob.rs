@@ -0,0 +1,205 @@
+// glob matching with gitignore semantics
+// * and ? don't match /, ** matches any number of directories
+// [...] matches a character class, [!...] or [^...] its complement
+// \ escapes the next character
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let path = path.chars().collect::<Vec<_>>();
+    wild(&pattern, &path)
+}
+
+// check if a pattern uses any glob syntax
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '\\'])
+}
+
+fn wild(p: &[char], t: &[char]) -> bool {
+    let mut pi = 0;
+    let mut ti = 0;
+
+    while pi < p.len() {
+        match p[pi] {
+            '*' => {
+                let mut end = pi + 1;
+                while end < p.len() && p[end] == '*' {
+                    end += 1;
+                }
+
+                let double = end - pi > 1;
+                let at_start = pi == 0 || p[pi - 1] == '/';
+                let at_end = end == p.len() || p[end] == '/';
+
+                if double && at_start && at_end {
+                    // trailing ** matches everything left
+                    if end == p.len() {
+                        return true;
+                    }
+
+                    // **/ matches zero or more directories
+                    let rest = &p[end + 1..];
+                    let mut i = ti;
+                    loop {
+                        if wild(rest, &t[i..]) {
+                            return true;
+                        }
+                        match t[i..].iter().position(|c| *c == '/') {
+                            Some(k) => i += k + 1,
+                            None => return false,
+                        }
+                    }
+                }
+
+                // * matches any run of characters within a directory
+                let rest = &p[end..];
+                for i in ti..=t.len() {
+                    if wild(rest, &t[i..]) {
+                        return true;
+                    }
+                    if i < t.len() && t[i] == '/' {
+                        break;
+                    }
+                }
+                return false;
+            }
+            '?' => {
+                if ti >= t.len() || t[ti] == '/' {
+                    return false;
+                }
+                pi += 1;
+                ti += 1;
+            }
+            '[' => match class(&p[pi..], t.get(ti).copied()) {
+                Some((matched, len)) => {
+                    if !matched {
+                        return false;
+                    }
+                    pi += len;
+                    ti += 1;
+                }
+                // no closing bracket, [ is literal
+                None => {
+                    if t.get(ti) != Some(&'[') {
+                        return false;
+                    }
+                    pi += 1;
+                    ti += 1;
+                }
+            },
+            c => {
+                let (c, len) = if c == '\\' && pi + 1 < p.len() {
+                    (p[pi + 1], 2)
+                } else {
+                    (c, 1)
+                };
+
+                if t.get(ti) != Some(&c) {
+                    return false;
+                }
+                pi += len;
+                ti += 1;
+            }
+        }
+    }
+
+    ti == t.len()
+}
+
+// match a character against the class at the start of p
+// returns whether it matched and the length of the class, None if the class isn't closed
+fn class(p: &[char], c: Option<char>) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(p.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+
+    loop {
+        let mut lo = *p.get(i)?;
+
+        if lo == ']' && !first {
+            break;
+        }
+        first = false;
+
+        if lo == '\\' {
+            i += 1;
+            lo = *p.get(i)?;
+        }
+        i += 1;
+
+        // range a-z
+        let mut hi = lo;
+        if p.get(i) == Some(&'-') && p.get(i + 1).is_some_and(|c| *c != ']') {
+            hi = p[i + 1];
+            if hi == '\\' {
+                hi = *p.get(i + 2)?;
+                i += 1;
+            }
+            i += 2;
+        }
+
+        if c.is_some_and(|c| lo <= c && c <= hi && c != '/') {
+            matched = true;
+        }
+    }
+
+    // c is None at the end of the text, which never matches
+    Some((c.is_some() && matched != negated && c != Some('/'), i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let cases = [
+            ("a.txt", "a.txt", true),
+            ("a.txt", "b.txt", false),
+            ("*.txt", "a.txt", true),
+            ("*.txt", ".txt", true),
+            ("*.txt", "dir/a.txt", false),
+            ("*", "", true),
+            ("a*b*c", "axxbyyc", true),
+            ("a*b*c", "axxbyy", false),
+            ("?.rs", "a.rs", true),
+            ("?.rs", "ab.rs", false),
+            ("a?b", "a/b", false),
+            ("**", "a/b/c", true),
+            ("**/c", "c", true),
+            ("**/c", "a/b/c", true),
+            ("a/**", "a/b/c", true),
+            ("a/**/c", "a/c", true),
+            ("a/**/c", "a/b/d/c", true),
+            ("a/**/c", "a/b/d", false),
+            ("a**c", "abc", true),
+            ("a**c", "a/c", false),
+            ("[abc].rs", "b.rs", true),
+            ("[abc].rs", "d.rs", false),
+            ("[a-c]x", "cx", true),
+            ("[!a-c]x", "dx", true),
+            ("[^a-c]x", "ax", false),
+            ("[]]", "]", true),
+            ("[a-]", "-", true),
+            ("a[/]b", "a/b", false),
+            ("[ab", "[ab", true),
+            ("\\*", "*", true),
+            ("\\*", "a", false),
+            ("[\\]]", "]", true),
+        ];
+
+        for (pattern, path, expected) in cases {
+            assert_eq!(glob_match(pattern, path), expected, "{pattern} on {path}");
+        }
+    }
+
+    #[test]
+    fn globs() {
+        assert!(is_glob("*.rs"));
+        assert!(is_glob("a[bc]"));
+        assert!(!is_glob("src/main.rs"));
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Result;
+
+use super::glob_match;
+
+// one line of an ignore file
+// also used for the patterns of attribute files
+pub(super) struct Pattern {
+    glob: String,
+    negated: bool,  // starts with !, re-includes paths
+    dir_only: bool, // ends with /, only matches dirs
+    basename: bool, // has no /, matches the name at any depth
+}
+
+impl Pattern {
+    pub(super) fn parse(line: &str) -> Option<Self> {
+        // trailing spaces are ignored unless escaped
+        let mut line = line.trim_end_matches(['\n', '\r']);
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+
+        let basename = !line.contains('/');
+        let glob = line.strip_prefix('/').unwrap_or(line);
+
+        if glob.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            glob: glob.to_string(),
+            negated,
+            dir_only,
+            basename,
+        })
+    }
+
+    // path is relative to the dir of the ignore file
+    pub(super) fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        if self.basename {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            glob_match(&self.glob, name)
+        } else {
+            glob_match(&self.glob, path)
+        }
+    }
+}
+
+fn parse_file(path: &Path) -> Result<Vec<Pattern>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(Pattern::parse)
+        .collect())
+}
+
+// rules deciding which files in the working tree are ignored
+// from .midignore files, the repo's exclude file and the global ignore file
+pub struct Ignore {
+    root: PathBuf,
+    // global ignore file then the exclude file, applied below all .midignore files
+    base: Vec<Pattern>,
+    // patterns from the .midignore in each dir, loaded when first needed
+    dirs: HashMap<PathBuf, Vec<Pattern>>,
+}
+
+impl Ignore {
+    pub fn new(root: &Path, exclude: &Path, global: Option<&Path>) -> Result<Self> {
+        let mut base = Vec::new();
+
+        if let Some(global) = global {
+            base.extend(parse_file(global)?);
+        }
+        base.extend(parse_file(exclude)?);
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            base,
+            dirs: HashMap::new(),
+        })
+    }
+
+    // check if a path relative to the root is ignored
+    // everything in an ignored dir is ignored and can't be re-included
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> Result<bool> {
+        // the repo's own storage is always excluded
+        if path.components().next() == Some(Component::Normal(".mid".as_ref())) {
+            return Ok(true);
+        }
+
+        let mut parents = path
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect::<Vec<_>>();
+        parents.reverse();
+
+        for dir in parents {
+            if self.check(dir, true)? {
+                return Ok(true);
+            }
+        }
+
+        self.check(path, is_dir)
+    }
+
+    // check the path itself against the patterns, deepest ignore file first
+    fn check(&mut self, path: &Path, is_dir: bool) -> Result<bool> {
+        let dirs = path.ancestors().skip(1).collect::<Vec<_>>();
+
+        for dir in &dirs {
+            if !self.dirs.contains_key(*dir) {
+                let patterns = parse_file(&self.root.join(dir).join(".midignore"))?;
+                self.dirs.insert(dir.to_path_buf(), patterns);
+            }
+        }
+
+        for dir in dirs {
+            let rel = path.strip_prefix(dir)?.to_string_lossy();
+
+            // last matching pattern in a file wins
+            if let Some(pattern) = self.dirs[dir]
+                .iter()
+                .rev()
+                .find(|pattern| pattern.matches(&rel, is_dir))
+            {
+                return Ok(!pattern.negated);
+            }
+        }
+
+        let path = path.to_string_lossy();
+
+        Ok(self
+            .base
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(&path, is_dir))
+            .is_some_and(|pattern| !pattern.negated))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignored() {
+        let root = std::env::temp_dir().join(format!("mid-ignore-{}", std::process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(
+            root.join(".midignore"),
+            "# comment\n*.log\n!keep.log\nbuild/\n!build/keep\n/top.txt\ndocs/**/*.tmp\nspace\\ \n",
+        )
+        .unwrap();
+        fs::write(root.join("sub/.midignore"), "!*.log\nlocal\n").unwrap();
+        fs::write(root.join("exclude"), "secret\n").unwrap();
+
+        let mut ignore = Ignore::new(&root, &root.join("exclude"), None).unwrap();
+        let cases = [
+            ("a.log", false, true),
+            ("dir/a.log", false, true),
+            ("keep.log", false, false),
+            ("dir/keep.log", false, false),
+            ("build", true, true),
+            ("build", false, false),
+            ("build/out.o", false, true),
+            ("build/keep", false, true), // can't re-include inside an ignored dir
+            ("top.txt", false, true),
+            ("dir/top.txt", false, false),
+            ("docs/c.tmp", false, true),
+            ("docs/a/b/c.tmp", false, true),
+            ("other/c.tmp", false, false),
+            ("sub/a.log", false, false), // deeper ignore file wins
+            ("sub/local", false, true),
+            ("local", false, false),
+            ("space ", false, true),
+            ("secret", false, true),
+            ("dir/secret", true, true),
+            (".mid", true, true),
+            (".mid/objects", true, true),
+            ("src/main.rs", false, false),
+        ];
+
+        for (path, is_dir, expected) in cases {
+            assert_eq!(
+                ignore.is_ignored(Path::new(path), is_dir).unwrap(),
+                expected,
+                "{path}"
+            );
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
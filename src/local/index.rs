@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, Metadata};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{mode_permissions, FileHash};
+
+// stat data of a file in the working tree along with the hash of its contents
+// lets us skip hashing files that haven't changed since they were last hashed
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub hash: FileHash,
+    pub mode: u32,
+    pub size: u64,
+    pub mtime: i64, // nanoseconds
+    pub ctime: i64, // nanoseconds
+    pub ino: u64,
+}
+
+impl IndexEntry {
+    pub fn new(hash: FileHash, meta: &Metadata) -> Self {
+        Self {
+            hash,
+            mode: meta.mode(),
+            size: meta.size(),
+            mtime: meta.mtime() * 1_000_000_000 + meta.mtime_nsec(),
+            ctime: meta.ctime() * 1_000_000_000 + meta.ctime_nsec(),
+            ino: meta.ino(),
+        }
+    }
+
+    // entry for a file whose stat data is unknown
+    // never matches a file so it is always rehashed
+    pub fn unknown(hash: FileHash) -> Self {
+        Self {
+            hash,
+            mode: 0,
+            size: 0,
+            mtime: 0,
+            ctime: 0,
+            ino: 0,
+        }
+    }
+
+    // check if the stat data still describes the file
+    pub fn matches(&self, meta: &Metadata) -> bool {
+        let new = Self::new(self.hash, meta);
+        self.mtime != 0 && *self == new
+    }
+}
+
+// index of every tracked file, stored in .mid/index.json
+// entries hold the staged hash of each file, and the stat data of the working tree file
+// when it last had that content
+#[derive(Default, Serialize, Deserialize)]
+pub struct Index {
+    pub entries: BTreeMap<PathBuf, IndexEntry>,
+
+    // token of the filesystem monitor from the last full status
+    // and the untracked files it found, lets the next status skip walking the tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub untracked: Vec<PathBuf>,
+
+    // when the index was last written, from the mtime of index.json
+    // files modified at or after this time may have changed without their stat data changing
+    #[serde(skip)]
+    written: i64,
+
+    // files hashed since the index was loaded
+    #[serde(skip)]
+    updated: BTreeSet<PathBuf>,
+}
+
+impl Index {
+    // load index from path, empty if it doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !fs::exists(path)? {
+            return Ok(Self::default());
+        }
+
+        let file = File::open(path)?;
+        let meta = file.metadata()?;
+
+        let mut index: Self = serde_json::from_reader(file)?;
+        index.written = meta.mtime() * 1_000_000_000 + meta.mtime_nsec();
+
+        Ok(index)
+    }
+
+    // racily clean entries that weren't hashed again lose their stat data
+    // otherwise writing the index would move written past them and they would be trusted
+    // entries hashed in this run are checked against the new written time when loaded
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        for (path, entry) in &mut self.entries {
+            if entry.mtime >= self.written && !self.updated.contains(path) {
+                *entry = IndexEntry::unknown(entry.hash);
+            }
+        }
+
+        let file = File::create(path)?;
+        serde_json::to_writer(&file, self)?;
+
+        let meta = file.metadata()?;
+        self.written = meta.mtime() * 1_000_000_000 + meta.mtime_nsec();
+        self.updated.clear();
+        Ok(())
+    }
+
+    // hash of the file at path if it hasn't changed since it was last hashed
+    pub fn cached(&self, path: &Path, meta: &Metadata) -> Option<FileHash> {
+        let entry = self.entries.get(path)?;
+
+        // racily clean, the file could have been changed in the same tick it was hashed
+        if entry.mtime >= self.written {
+            return None;
+        }
+
+        entry.matches(meta).then_some(entry.hash)
+    }
+
+    // record that the file at path with this stat data has hash
+    pub fn update(&mut self, path: &Path, hash: FileHash, meta: &Metadata) {
+        self.entries
+            .insert(path.to_path_buf(), IndexEntry::new(hash, meta));
+        self.updated.insert(path.to_path_buf());
+    }
+
+    // hash and mode of a file whose stat data is known, without looking at the file
+    // only for files the filesystem monitor says haven't changed
+    pub fn known(&self, path: &Path) -> Option<(FileHash, u32)> {
+        let entry = self.entries.get(path)?;
+        (entry.mtime != 0).then_some((entry.hash, mode_permissions(entry.mode)))
+    }
+
+    // make the entries match the staged files
+    // entries with a different hash lose their stat data
+    pub fn sync(&mut self, staged: &BTreeMap<PathBuf, (FileHash, u32)>) {
+        self.entries.retain(|path, _| staged.contains_key(path));
+
+        for (path, (hash, _)) in staged {
+            match self.entries.get(path) {
+                Some(entry) if entry.hash == *hash => {}
+                _ => {
+                    self.entries
+                        .insert(path.clone(), IndexEntry::unknown(*hash));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::{BinaryHeap, HashMap};
+
+use anyhow::Result;
+use hex::ToHex;
+
+use super::{format_time, ComHash, Repo};
+
+// which commits log shows
+#[derive(Default)]
+pub struct LogFilter {
+    pub limit: Option<usize>,   // at most this many commits, -n
+    pub since: Option<u64>,     // only commits made at or after this time
+    pub until: Option<u64>,     // only commits made at or before this time
+    pub author: Option<String>, // only commits whose author contains this
+    pub reverse: bool,          // oldest first, applied after the limit
+}
+
+impl Repo {
+    // commits reachable from starts along their parent links, newest first
+    // history from several starts is interleaved by commit time
+    // but a commit never comes before one built on top of it
+    pub fn log(&self, starts: &[ComHash], filter: &LogFilter) -> Vec<ComHash> {
+        // number of reachable commits with each commit as their parent
+        let mut children: HashMap<ComHash, usize> = HashMap::new();
+        for start in starts {
+            if start.0 == [0; 20] || children.contains_key(start) {
+                continue;
+            }
+            children.insert(*start, 0);
+
+            let mut commit = *start;
+            loop {
+                let prev = self.get_commit(commit).prev;
+                if prev.0 == [0; 20] {
+                    break;
+                }
+
+                let count = children.entry(prev).or_default();
+                *count += 1;
+                if *count > 1 {
+                    break; // the rest was counted from another start
+                }
+                commit = prev;
+            }
+        }
+
+        // commits whose children have all been shown, newest first
+        let mut queue = children
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(hash, _)| (self.get_commit(*hash).time, hash.0))
+            .collect::<BinaryHeap<_>>();
+
+        let mut commits = Vec::new();
+        while let Some((_, hash)) = queue.pop() {
+            let hash = ComHash(hash);
+            if filter.limit.is_some_and(|limit| commits.len() >= limit) {
+                break;
+            }
+
+            let commit = self.get_commit(hash);
+            if let Some(count) = children.get_mut(&commit.prev) {
+                *count -= 1;
+                if *count == 0 {
+                    queue.push((self.get_commit(commit.prev).time, commit.prev.0));
+                }
+            }
+
+            if filter.since.is_some_and(|since| commit.time < since)
+                || filter.until.is_some_and(|until| commit.time > until)
+                || filter
+                    .author
+                    .as_ref()
+                    .is_some_and(|author| !commit.author.contains(author.as_str()))
+            {
+                continue;
+            }
+
+            commits.push(hash);
+        }
+
+        if filter.reverse {
+            commits.reverse();
+        }
+        commits
+    }
+
+    // expand the placeholders in a log format for one commit
+    // %H hash, %h short hash, %an author, %ad date, %at unix time
+    // %s subject, %b body, %N notes, %n newline, %% percent sign
+    pub fn format_commit(&self, hash: ComHash, format: &str) -> Result<String> {
+        let commit = self.get_commit(hash);
+        let hex = hash.0.encode_hex::<String>();
+        let (subject, body) = commit.msg.split_once('\n').unwrap_or((&commit.msg, ""));
+
+        let mut out = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+
+            // two letter placeholders start with a
+            let mut name = chars.next().map(String::from).unwrap_or_default();
+            if name == "a" {
+                name.extend(chars.next_if(|c| matches!(c, 'n' | 'd' | 't')));
+            }
+
+            match name.as_str() {
+                "H" => out.push_str(&hex),
+                "h" => out.push_str(&hex[..7]),
+                "an" => out.push_str(&commit.author),
+                "ad" => out.push_str(&format_time(commit.time)),
+                "at" => out.push_str(&commit.time.to_string()),
+                "s" => out.push_str(subject),
+                "b" => out.push_str(body.trim_start_matches('\n')),
+                "N" => out.push_str(&self.get_note(hash)?.unwrap_or_default()),
+                "n" => out.push('\n'),
+                "%" => out.push('%'),
+                // unknown placeholders are left as they are
+                other => {
+                    out.push('%');
+                    out.push_str(other);
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
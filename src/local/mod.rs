@@ -1,12 +1,52 @@
 // for interacting with state of local respository
+mod attributes;
 mod branch;
+mod checkout;
+mod collision;
 mod commit;
+mod config;
+mod diff;
+mod filter;
 mod fs;
+mod glob;
+mod ignore;
+mod index;
+mod log;
+mod monitor;
+mod notes;
 mod object;
+mod patch;
+mod pathspec;
+mod reflog;
 mod repo;
+mod sign;
+mod stage;
+mod stash;
+mod status;
+mod tag;
+mod time;
+mod walk;
 
+pub use attributes::*;
 pub use branch::*;
+pub use checkout::*;
+pub use collision::*;
 pub use commit::*;
+pub use config::*;
+pub use filter::*;
 pub use fs::*;
+pub use glob::*;
+pub use ignore::*;
+pub use index::*;
+pub use log::*;
+pub use monitor::*;
 pub use object::*;
+pub use pathspec::*;
+pub use reflog::*;
 pub use repo::*;
+pub use sign::*;
+pub use stash::*;
+pub use status::*;
+pub use tag::*;
+pub use time::*;
+pub use walk::*;
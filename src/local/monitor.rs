@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use serde::{Deserialize, Serialize};
+
+use super::{now, walk_tree};
+
+// answer to a query for the paths changed since a token
+#[derive(Serialize, Deserialize)]
+pub struct MonitorReply {
+    pub token: String, // pass to the next query
+    // paths relative to the root that changed since the old token
+    // a changed dir stands for everything in it
+    // None when the monitor can't tell and everything has to be scanned
+    pub changed: Option<Vec<PathBuf>>,
+}
+
+// what the monitor has seen since it started
+struct State {
+    id: String,                     // tokens from other runs of the monitor aren't valid
+    seq: u64,                       // number of events seen
+    overflow: u64,                  // seq of the last time events were dropped
+    changed: HashMap<PathBuf, u64>, // seq of the last event for each path
+    dirs: HashMap<WatchDescriptor, PathBuf>, // watched dirs relative to the root
+    cookie: u64,                    // last cookie file seen in .mid
+}
+
+impl State {
+    fn token(&self) -> String {
+        format!("{}:{}", self.id, self.seq)
+    }
+
+    fn reply(&self, token: &str) -> MonitorReply {
+        let since = token
+            .split_once(':')
+            .filter(|(id, _)| *id == self.id)
+            .and_then(|(_, seq)| seq.parse::<u64>().ok())
+            .filter(|seq| *seq >= self.overflow && *seq <= self.seq);
+
+        MonitorReply {
+            token: self.token(),
+            changed: since.map(|since| {
+                self.changed
+                    .iter()
+                    .filter(|(_, seq)| **seq > since)
+                    .map(|(path, _)| path.clone())
+                    .collect()
+            }),
+        }
+    }
+
+    fn record(&mut self, path: PathBuf) {
+        self.seq += 1;
+        self.changed.insert(path, self.seq);
+    }
+}
+
+const MASK: WatchMask = WatchMask::CREATE
+    .union(WatchMask::DELETE)
+    .union(WatchMask::MODIFY)
+    .union(WatchMask::ATTRIB)
+    .union(WatchMask::CLOSE_WRITE)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::MOVED_TO)
+    .union(WatchMask::DELETE_SELF)
+    .union(WatchMask::ONLYDIR);
+
+// watch a dir and every dir in it, skipping the repo's storage
+// each dir is watched before it is read so nothing created in it is missed
+fn watch_tree(watches: &mut Watches, root: &Path, rel: &Path, state: &Mutex<State>) {
+    let mut watch = |rel: &Path| match watches.add(root.join(rel), MASK) {
+        Ok(wd) => {
+            state.lock().unwrap().dirs.insert(wd, rel.to_path_buf());
+            true
+        }
+        Err(_) => false, // removed before we got to it
+    };
+
+    if rel == Path::new(".mid") || !watch(rel) {
+        return;
+    }
+    let _ = walk_tree(root, rel, None, |path, is_dir| Ok(is_dir && watch(path)));
+}
+
+// path of the monitor's socket in a repo's storage
+pub fn monitor_socket(mid: &Path) -> PathBuf {
+    mid.join("monitor.sock")
+}
+
+// run the monitor for the working tree at root until it is told to stop
+// queries are answered on a unix socket in .mid
+pub fn run_monitor(root: &Path, socket: &Path) -> Result<()> {
+    if UnixStream::connect(socket).is_ok() {
+        bail!("A monitor is already running for this repository");
+    }
+    let _ = fs::remove_file(socket); // left behind by a monitor that died
+    let listener = UnixListener::bind(socket)?;
+
+    let state = Arc::new(Mutex::new(State {
+        id: format!("{}-{}", std::process::id(), now()),
+        seq: 0,
+        overflow: 0,
+        changed: HashMap::new(),
+        dirs: HashMap::new(),
+        cookie: 0,
+    }));
+    let cookie_seen = Arc::new(Condvar::new());
+
+    let mut inotify = Inotify::init()?;
+    let mut watches = inotify.watches();
+    watch_tree(&mut watches, root, Path::new(""), &state);
+
+    // cookie files are written to .mid on every query
+    // once the event for one is read every change before the query has been seen
+    let mid = socket.parent().unwrap_or(root).to_path_buf();
+    let cookie_wd = watches.add(&mid, WatchMask::CREATE)?;
+
+    let events_state = state.clone();
+    let events_cookie = cookie_seen.clone();
+    let events_root = root.to_path_buf();
+    thread::spawn(move || {
+        let mut buffer = [0; 16384];
+        loop {
+            let Ok(events) = inotify.read_events_blocking(&mut buffer) else {
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            };
+
+            let mut new_dirs = Vec::new();
+            {
+                let mut state = events_state.lock().unwrap();
+                for event in events {
+                    if event.wd == cookie_wd {
+                        let cookie = event.name.and_then(|name| {
+                            name.to_str()?.strip_prefix("monitor-cookie-")?.parse().ok()
+                        });
+                        if let Some(cookie) = cookie {
+                            state.cookie = cookie;
+                            events_cookie.notify_all();
+                        }
+                        continue;
+                    }
+
+                    if event.mask.contains(EventMask::Q_OVERFLOW) {
+                        state.seq += 1;
+                        state.overflow = state.seq;
+                        continue;
+                    }
+
+                    let Some(dir) = state.dirs.get(&event.wd).cloned() else {
+                        continue;
+                    };
+
+                    if event.mask.contains(EventMask::IGNORED) {
+                        state.dirs.remove(&event.wd);
+                        continue;
+                    }
+
+                    let path = match event.name {
+                        Some(name) => dir.join(name),
+                        None => dir,
+                    };
+
+                    // dirs that appear need watching too, everything in them is new
+                    if event.mask.contains(EventMask::ISDIR)
+                        && event
+                            .mask
+                            .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+                    {
+                        new_dirs.push(path.clone());
+                    }
+
+                    state.record(path);
+                }
+            }
+
+            for dir in new_dirs {
+                watch_tree(&mut watches, &events_root, &dir, &events_state);
+            }
+        }
+    });
+
+    let mut cookies = 0;
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+
+        let mut line = String::new();
+        if BufReader::new(&stream).read_line(&mut line).is_err() {
+            continue;
+        }
+
+        match line.trim().split_once(' ') {
+            Some(("query", token)) => {
+                cookies += 1;
+                let cookie = mid.join(format!("monitor-cookie-{cookies}"));
+                let _ = fs::write(&cookie, "");
+
+                // wait for the events from before the query to be read
+                let (state, timeout) = cookie_seen
+                    .wait_timeout_while(state.lock().unwrap(), Duration::from_secs(1), |state| {
+                        state.cookie < cookies
+                    })
+                    .unwrap();
+                let _ = fs::remove_file(&cookie);
+
+                let mut reply = state.reply(token);
+                if timeout.timed_out() {
+                    reply.changed = None;
+                }
+                let _ = serde_json::to_writer(&mut stream, &reply);
+                let _ = stream.flush();
+            }
+            _ if line.trim() == "stop" => break,
+            _ => {}
+        }
+    }
+
+    fs::remove_file(socket)?;
+    Ok(())
+}
+
+// ask the monitor what changed since token
+// None if no monitor is running, the caller has to scan everything itself
+pub fn query_monitor(socket: &Path, token: Option<&str>) -> Option<MonitorReply> {
+    let mut stream = UnixStream::connect(socket).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+
+    writeln!(stream, "query {}", token.unwrap_or("none")).ok()?;
+    serde_json::from_reader(stream).ok()
+}
+
+// tell the monitor to exit
+pub fn stop_monitor(socket: &Path) -> Result<()> {
+    let Ok(mut stream) = UnixStream::connect(socket) else {
+        bail!("No monitor is running for this repository");
+    };
+    writeln!(stream, "stop")?;
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::ffi::OsStr;
+
+use anyhow::{bail, Result};
+use hex::ToHex;
+
+use super::{ComHash, Object, Repo};
+
+impl Repo {
+    // get the note attached to a commit
+    pub fn get_note(&self, commit: ComHash) -> Result<Option<String>> {
+        let Some(notes) = self.notes else {
+            return Ok(None);
+        };
+
+        let name = commit.0.encode_hex::<String>();
+
+        match self.get_dir(notes).objs.get(OsStr::new(&name)) {
+            Some(Object::File(hash, _)) => Ok(Some(
+                String::from_utf8_lossy(&self.read_file(*hash)?).into_owned(),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    // attach a note to a commit
+    // replaces an existing note only if force is set
+    pub fn add_note(&mut self, commit: ComHash, msg: String, force: bool) -> Result<()> {
+        if !force && self.get_note(commit)?.is_some() {
+            bail!("Commit already has a note, use -f to overwrite it");
+        }
+
+        let blob = self.new_file(msg.into_bytes());
+
+        let mut objs = self
+            .notes
+            .map(|notes| self.get_dir(notes).objs.clone())
+            .unwrap_or_default();
+        objs.insert(
+            commit.0.encode_hex::<String>().into(),
+            Object::File(blob, 0o644),
+        );
+
+        self.notes = Some(self.new_dir(objs));
+
+        Ok(())
+    }
+
+    // remove the note attached to a commit
+    pub fn remove_note(&mut self, commit: ComHash) -> Result<()> {
+        let Some(notes) = self.notes else {
+            bail!("Commit has no note");
+        };
+
+        let mut objs = self.get_dir(notes).objs.clone();
+
+        if objs
+            .remove(OsStr::new(&commit.0.encode_hex::<String>()))
+            .is_none()
+        {
+            bail!("Commit has no note");
+        }
+
+        self.notes = (!objs.is_empty()).then(|| self.new_dir(objs));
+
+        Ok(())
+    }
+}
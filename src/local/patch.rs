@@ -0,0 +1,386 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use super::{Object, Repo};
+
+// one line of a hunk, including its newline if it has one
+#[derive(Clone, PartialEq, Eq)]
+pub enum HunkLine {
+    Context(Vec<u8>),
+    Removed(Vec<u8>),
+    Added(Vec<u8>),
+}
+
+// a change to one region of a file
+// starts are 1 based line numbers like in the @@ header
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+impl Hunk {
+    // lines the file has before the hunk is applied
+    fn old_lines(&self) -> Vec<&[u8]> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Removed(text) => Some(text.as_slice()),
+                HunkLine::Added(_) => None,
+            })
+            .collect()
+    }
+
+    // lines the file has after the hunk is applied
+    fn new_lines(&self) -> Vec<&[u8]> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Added(text) => Some(text.as_slice()),
+                HunkLine::Removed(_) => None,
+            })
+            .collect()
+    }
+}
+
+// changes to one file, paths are None for /dev/null when a file is created or deleted
+pub struct FilePatch {
+    pub old: Option<PathBuf>,
+    pub new: Option<PathBuf>,
+    pub hunks: Vec<Hunk>,
+}
+
+// split contents into lines, each keeping its newline
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|c| *c == b'\n').collect()
+}
+
+// path from a ---/+++ line, without the a/ or b/ prefix and any timestamp
+// only plain relative paths outside .mid are accepted
+fn parse_path(line: &[u8]) -> Result<Option<PathBuf>> {
+    let line = String::from_utf8_lossy(line);
+    let path = line.split('\t').next().unwrap_or_default().trim_end();
+
+    if path == "/dev/null" {
+        return Ok(None);
+    }
+
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    let path = PathBuf::from(path);
+
+    if path.as_os_str().is_empty()
+        || path.starts_with(".mid")
+        || !path
+            .components()
+            .all(|comp| matches!(comp, Component::Normal(_)))
+    {
+        bail!("Patch has an invalid path '{}'", path.display());
+    }
+    Ok(Some(path))
+}
+
+// start and length from one side of a hunk header, -l,s or +l,s
+fn parse_range(range: &str) -> Result<(usize, usize)> {
+    let (start, len) = match range.split_once(',') {
+        Some((start, len)) => (start, len.parse()?),
+        None => (range, 1),
+    };
+    Ok((start.parse()?, len))
+}
+
+// parse a unified diff of any number of files
+pub fn parse_patch(patch: &[u8]) -> Result<Vec<FilePatch>> {
+    let lines = split_lines(patch);
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        if line.starts_with(b"--- ") && lines.get(i + 1).is_some_and(|l| l.starts_with(b"+++ ")) {
+            files.push(FilePatch {
+                old: parse_path(line[4..].trim_ascii_end())?,
+                new: parse_path(lines[i + 1][4..].trim_ascii_end())?,
+                hunks: Vec::new(),
+            });
+            i += 2;
+            continue;
+        }
+
+        if !line.starts_with(b"@@ ") {
+            // diff --git, index and other header lines
+            i += 1;
+            continue;
+        }
+
+        let file = files
+            .last_mut()
+            .context("Hunk found before any file header")?;
+
+        // @@ -l,s +l,s @@ optional section heading
+        let header = String::from_utf8_lossy(line);
+        let mut parts = header.split_whitespace().skip(1);
+        let (old_start, old_len) = match parts.next().and_then(|p| p.strip_prefix('-')) {
+            Some(range) => parse_range(range)?,
+            None => bail!("Malformed hunk header '{}'", header.trim_end()),
+        };
+        let (new_start, new_len) = match parts.next().and_then(|p| p.strip_prefix('+')) {
+            Some(range) => parse_range(range)?,
+            None => bail!("Malformed hunk header '{}'", header.trim_end()),
+        };
+        i += 1;
+
+        let mut hunk = Hunk {
+            old_start,
+            old_len,
+            new_start,
+            new_len,
+            lines: Vec::new(),
+        };
+
+        let (mut old, mut new) = (0, 0);
+        while old < old_len || new < new_len {
+            let Some(line) = lines.get(i) else {
+                bail!("Patch ends in the middle of a hunk");
+            };
+            i += 1;
+
+            // some editors strip the space from empty context lines
+            let (kind, text) = match line.split_first() {
+                Some((b'\n', _)) => (b' ', &b"\n"[..]),
+                Some((kind, text)) => (*kind, text),
+                None => bail!("Patch ends in the middle of a hunk"),
+            };
+
+            let text = text.to_vec();
+            match kind {
+                b' ' => {
+                    hunk.lines.push(HunkLine::Context(text));
+                    old += 1;
+                    new += 1;
+                }
+                b'-' => {
+                    hunk.lines.push(HunkLine::Removed(text));
+                    old += 1;
+                }
+                b'+' => {
+                    hunk.lines.push(HunkLine::Added(text));
+                    new += 1;
+                }
+                b'\\' => strip_newline(&mut hunk.lines),
+                _ => bail!(
+                    "Unexpected line in hunk: '{}'",
+                    String::from_utf8_lossy(line)
+                ),
+            }
+        }
+
+        // the marker for the last line comes after the counts are used up
+        if lines.get(i).is_some_and(|line| line.starts_with(b"\\")) {
+            strip_newline(&mut hunk.lines);
+            i += 1;
+        }
+
+        file.hunks.push(hunk);
+    }
+
+    Ok(files)
+}
+
+// \ No newline at end of file applies to the line before it
+fn strip_newline(lines: &mut [HunkLine]) {
+    if let Some(HunkLine::Context(text) | HunkLine::Removed(text) | HunkLine::Added(text)) =
+        lines.last_mut()
+    {
+        if text.last() == Some(&b'\n') {
+            text.pop();
+        }
+    }
+}
+
+// apply hunks to the contents of a file
+// hunks are found near the line numbers in their header in case the file moved around
+pub fn apply_hunks(data: &[u8], hunks: &[Hunk], path: &Path) -> Result<Vec<u8>> {
+    let lines = split_lines(data);
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    for (n, hunk) in hunks.iter().enumerate() {
+        let old = hunk.old_lines();
+
+        // an empty old side is placed after old_start rather than at it
+        let expected = match hunk.old_len {
+            0 => hunk.old_start,
+            _ => hunk.old_start.saturating_sub(1),
+        };
+
+        let fits =
+            |at: usize| at + old.len() <= lines.len() && lines[at..at + old.len()] == old[..];
+        let found = (0..=lines.len())
+            .flat_map(|offset| [expected.checked_add(offset), expected.checked_sub(offset)])
+            .flatten()
+            .filter(|at| *at >= pos && *at <= lines.len())
+            .find(|at| fits(*at));
+
+        let Some(at) = found else {
+            bail!("Hunk {} doesn't apply to '{}'", n + 1, path.display());
+        };
+
+        out.extend(lines[pos..at].concat());
+        out.extend(hunk.new_lines().concat());
+        pos = at + old.len();
+    }
+
+    out.extend(lines[pos..].concat());
+    Ok(out)
+}
+
+impl Repo {
+    // apply a unified diff to the staged version of files
+    // new blobs are staged without touching the working tree
+    // returns the paths that were changed
+    pub fn apply_to_index(&mut self, patch: &[u8]) -> Result<Vec<PathBuf>> {
+        let staged_tree = self.staged_tree();
+        let mut edits = Vec::new();
+        let mut changed = Vec::new();
+
+        for file in parse_patch(patch)? {
+            let (data, permissions) = match &file.old {
+                Some(path) => match self.tree_lookup(staged_tree, path) {
+                    Some(Object::File(hash, permissions)) => (self.read_file(hash)?, permissions),
+                    _ => bail!("'{}' isn't in the index", path.display()),
+                },
+                None => (Vec::new(), 0o644),
+            };
+
+            let path = file.new.as_ref().or(file.old.as_ref());
+            let path = path.context("Patch has a file without a path")?;
+            let data = apply_hunks(&data, &file.hunks, path)?;
+
+            match &file.new {
+                Some(new) => {
+                    if file.old.is_none() && self.tree_lookup(staged_tree, new).is_some() {
+                        bail!("'{}' already exists in the index", new.display());
+                    }
+
+                    let hash = self.new_file(data);
+                    edits.push((new.clone(), Some(Object::File(hash, permissions))));
+                    changed.push(new.clone());
+                }
+                None => {
+                    if !data.is_empty() {
+                        bail!("Deleted file '{}' still has contents", path.display());
+                    }
+                }
+            }
+
+            // deleted or renamed away
+            if let Some(old) = file
+                .old
+                .as_ref()
+                .filter(|old| file.new.as_ref() != Some(*old))
+            {
+                edits.push((old.clone(), None));
+                changed.push(old.clone());
+            }
+        }
+
+        self.index = Some(self.edit_tree(staged_tree, edits)?);
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // parse a patch of one file and apply it to data
+    fn apply(data: &str, patch: &str) -> String {
+        let files = parse_patch(patch.as_bytes()).unwrap();
+        assert_eq!(files.len(), 1);
+        let out = apply_hunks(data.as_bytes(), &files[0].hunks, Path::new("f")).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn offset_hunks() {
+        let patch = "--- a/f\n+++ b/f\n@@ -2,3 +2,3 @@\n b\n-c\n+C\n d\n";
+        assert_eq!(apply("a\nb\nc\nd\n", patch), "a\nb\nC\nd\n");
+
+        // lines were added above and removed below the hunk since it was made
+        assert_eq!(apply("x\ny\na\nb\nc\nd\n", patch), "x\ny\na\nb\nC\nd\n");
+        assert_eq!(apply("b\nc\nd\n", patch), "b\nC\nd\n");
+
+        // hunks after an offset one still apply in order
+        let patch = "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-a\n+A\n@@ -3 +3 @@\n-c\n+C\n";
+        assert_eq!(apply("x\na\nb\nc\n", patch), "x\nA\nb\nC\n");
+
+        let files = parse_patch(b"--- a/f\n+++ b/f\n@@ -1 +1 @@\n-z\n+Z\n").unwrap();
+        assert!(apply_hunks(b"a\nb\n", &files[0].hunks, Path::new("f")).is_err());
+    }
+
+    #[test]
+    fn created_and_deleted_files() {
+        let patch = "diff --git a/f b/f\nnew file mode 100644\n--- /dev/null\n+++ b/f\n@@ -0,0 +1,2 @@\n+a\n+b\n";
+        let files = parse_patch(patch.as_bytes()).unwrap();
+        assert_eq!(files[0].old, None);
+        assert_eq!(files[0].new, Some(PathBuf::from("f")));
+        assert_eq!(apply("", patch), "a\nb\n");
+
+        let patch = "--- a/dir/f\t2024-01-01 00:00:00\n+++ /dev/null\n@@ -1,2 +0,0 @@\n-a\n-b\n";
+        let files = parse_patch(patch.as_bytes()).unwrap();
+        assert_eq!(files[0].old, Some(PathBuf::from("dir/f")));
+        assert_eq!(files[0].new, None);
+        assert_eq!(apply("a\nb\n", patch), "");
+    }
+
+    #[test]
+    fn invalid_paths() {
+        for path in [
+            "../evil",
+            "a/../../evil",
+            "/etc/passwd",
+            ".mid/repo.json",
+            "./f",
+            "a//",
+        ] {
+            let patch = format!("--- /dev/null\n+++ {path}\n@@ -0,0 +1 @@\n+x\n");
+            assert!(parse_patch(patch.as_bytes()).is_err(), "{path}");
+        }
+
+        let patch = "--- /dev/null\n+++ b/dir/f\n@@ -0,0 +1 @@\n+x\n";
+        assert!(parse_patch(patch.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn no_newline_marker() {
+        // after the last line of the hunk, once the counts are used up
+        let patch = "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n\\ No newline at end of file\n";
+        assert_eq!(apply("a\nb\n", patch), "a\nc");
+
+        // on the old side, before the added lines
+        let patch = "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+b\n";
+        assert_eq!(apply("a\nb", patch), "a\nb\n");
+
+        // on a context line both sides share
+        let patch = "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n-a\n+A\n b\n\\ No newline at end of file\n";
+        assert_eq!(apply("a\nb", patch), "A\nb");
+    }
+
+    #[test]
+    fn empty_context_lines() {
+        // editors often strip the space from empty context lines
+        let patch = "--- a/f\n+++ b/f\n@@ -1,4 +1,4 @@\n a\n\n-c\n+C\n\n";
+        assert_eq!(apply("a\n\nc\n\n", patch), "a\n\nC\n\n");
+
+        // with the space kept it means the same
+        let patch = "--- a/f\n+++ b/f\n@@ -1,4 +1,4 @@\n a\n \n-c\n+C\n \n";
+        assert_eq!(apply("a\n\nc\n\n", patch), "a\n\nC\n\n");
+    }
+}
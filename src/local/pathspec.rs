@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+use super::{glob_match, is_glob, walk_tree, DirHash, FileHash, Repo};
+
+// one pattern of a pathspec, relative to the root of the repo
+struct Spec {
+    pattern: String,
+    glob: bool,
+}
+
+impl Spec {
+    // a spec matches a path if it matches the path or one of the dirs containing it
+    fn matches(&self, path: &Path) -> bool {
+        if self.pattern.is_empty() {
+            return true; // root of the repo
+        }
+
+        if !self.glob {
+            return path.starts_with(&self.pattern);
+        }
+
+        path.ancestors()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .any(|dir| glob_match(&self.pattern, &dir.to_string_lossy()))
+    }
+}
+
+// paths selected by the user
+// patterns can use *, **, ? and [...], patterns starting with :(exclude), :! or :^ exclude paths
+pub struct Pathspec {
+    include: Vec<Spec>,
+    exclude: Vec<Spec>,
+}
+
+impl Pathspec {
+    // parse patterns given relative to the directory the user ran the command from
+    pub fn parse(repo: &Repo, args: &[impl AsRef<str>]) -> Result<Self> {
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+
+        for arg in args {
+            let arg = arg.as_ref();
+
+            let (excluded, pattern) = match arg
+                .strip_prefix(":(exclude)")
+                .or_else(|| arg.strip_prefix(":!"))
+                .or_else(|| arg.strip_prefix(":^"))
+            {
+                Some(pattern) => (true, pattern),
+                None => (false, arg),
+            };
+
+            let path = repo.user_path(pattern)?;
+            let pattern = if path == Path::new(".") {
+                String::new()
+            } else {
+                path.to_string_lossy().into_owned()
+            };
+
+            let spec = Spec {
+                glob: is_glob(&pattern),
+                pattern,
+            };
+
+            if excluded {
+                exclude.push(spec);
+            } else {
+                include.push(spec);
+            }
+        }
+
+        // only excludes means everything else
+        if include.is_empty() && !exclude.is_empty() {
+            include.push(Spec {
+                pattern: String::new(),
+                glob: false,
+            });
+        }
+
+        Ok(Self { include, exclude })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty()
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        self.include.iter().any(|spec| spec.matches(path))
+            && !self.exclude.iter().any(|spec| spec.matches(path))
+    }
+
+    // patterns that don't match any of the paths
+    pub fn unmatched<'a>(&'a self, paths: &[PathBuf]) -> Vec<&'a str> {
+        self.include
+            .iter()
+            .filter(|spec| !paths.iter().any(|path| spec.matches(path)))
+            .map(|spec| spec.pattern.as_str())
+            .collect()
+    }
+}
+
+// dirs containing tracked files, the root is the empty path
+pub fn tracked_dirs(tracked: &BTreeMap<PathBuf, (FileHash, u32)>) -> BTreeSet<PathBuf> {
+    tracked
+        .keys()
+        .flat_map(|path| path.ancestors().skip(1))
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+impl Repo {
+    // files matching the pathspec, in the working tree or tracked in a tree
+    // ignored files are only included if they are tracked
+    pub fn expand_pathspec(&mut self, spec: &Pathspec, tree: DirHash) -> Result<Vec<PathBuf>> {
+        let root = self.work_tree()?;
+        let tracked = self.flatten_tree(tree);
+        let tracked_dirs = tracked_dirs(&tracked);
+
+        // the monitor knows which untracked files there are without walking the tree
+        let mut files = BTreeSet::new();
+        self.refresh_monitor();
+        match self.monitored_untracked(&root, &tracked, &tracked_dirs)? {
+            Some(untracked) => files.extend(untracked),
+            None => self.list_files(Path::new(""), &tracked, &tracked_dirs, &mut files)?,
+        }
+
+        // tracked files missing from the working tree
+        files.extend(tracked.into_keys());
+
+        let files = files
+            .into_iter()
+            .filter(|path| spec.matches(path))
+            .collect::<Vec<_>>();
+
+        if let Some(pattern) = spec.unmatched(&files).first() {
+            let path = root.join(pattern);
+            if !is_glob(pattern)
+                && path.exists()
+                && self.is_ignored(Path::new(pattern), path.is_dir())?
+            {
+                bail!("The path '{}' is ignored by .midignore", pattern);
+            }
+            bail!("Pathspec '{}' did not match any files", pattern);
+        }
+
+        Ok(files)
+    }
+
+    // files matching a pathspec that are tracked in any of the trees
+    // used where untracked files in the working tree don't matter
+    pub fn tracked_matches(&self, spec: &Pathspec, trees: &[DirHash]) -> Result<Vec<PathBuf>> {
+        let mut files = trees
+            .iter()
+            .flat_map(|tree| self.flatten_tree(*tree).into_keys())
+            .filter(|path| spec.matches(path))
+            .collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+
+        if let Some(pattern) = spec.unmatched(&files).first() {
+            bail!("Pathspec '{}' did not match any tracked files", pattern);
+        }
+
+        Ok(files)
+    }
+
+    // files in the working tree below rel that are tracked or not ignored
+    // dirs are read in parallel on the worker pool
+    pub(super) fn list_files(
+        &mut self,
+        rel: &Path,
+        tracked: &BTreeMap<PathBuf, (FileHash, u32)>,
+        tracked_dirs: &BTreeSet<PathBuf>,
+        files: &mut BTreeSet<PathBuf>,
+    ) -> Result<()> {
+        let root = self.work_tree()?;
+        let pool = self.pool()?;
+
+        walk_tree(&root, rel, Some(&pool), |path, is_dir| {
+            if !tracked.contains_key(path)
+                && !tracked_dirs.contains(path)
+                && self.is_ignored(path, is_dir)?
+            {
+                return Ok(false);
+            }
+
+            if !is_dir {
+                files.insert(path.to_path_buf());
+            }
+            Ok(is_dir)
+        })
+    }
+}
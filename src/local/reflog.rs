@@ -0,0 +1,63 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::ComHash;
+
+// one movement of a ref
+// reflogs are append only and stored as one json entry per line in .mid/logs
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReflogEntry {
+    pub old: ComHash,
+    pub new: ComHash,
+    pub who: String,
+    pub time: u64,
+    pub msg: String,
+}
+
+// path of the log for a ref in the logs dir
+// HEAD is logged at logs/HEAD, branches at logs/refs/<name>
+pub fn reflog_path(logs: &Path, name: &str) -> PathBuf {
+    if name == "HEAD" {
+        logs.join("HEAD")
+    } else {
+        logs.join("refs").join(name)
+    }
+}
+
+// read all entries of a reflog, oldest first
+pub fn read_reflog(logs: &Path, name: &str) -> Result<Vec<ReflogEntry>> {
+    let path = reflog_path(logs, name);
+
+    if !fs::exists(&path)? {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.is_empty() {
+            entries.push(serde_json::from_str(&line)?);
+        }
+    }
+
+    Ok(entries)
+}
+
+// add entry to the end of a reflog
+pub fn append_reflog(logs: &Path, name: &str, entry: &ReflogEntry) -> Result<()> {
+    let path = reflog_path(logs, name);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+
+    Ok(())
+}
@@ -1,25 +1,53 @@
 use anyhow::{bail, Context, Result};
+use ed25519_dalek::SigningKey;
 use hex::ToHex;
 use serde::{Deserialize, Serialize};
-use std::cell::UnsafeCell;
-use std::collections::HashMap;
+use std::cell::{OnceCell, RefCell, UnsafeCell};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
 use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::fs::{DirBuilder, File};
-use std::path::{Components, Path, PathBuf};
+use std::io;
+use std::path::{Component, Components, Path, PathBuf};
 use std::pin::Pin;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use super::{
-    hash_file, Branch, ComHash, Commit, DirHash, DirObject, FileHash, FileObject, FileState,
-    Object, ObjectState,
+    append_reflog, check_entry_name, get_permissions, hash_data, load_signing_key, monitor_socket,
+    now, query_monitor, read_reflog, run_filter, set_permissions, to_crlf, tracked_dirs,
+    Attributes, Attrs, Branch, CleanJob, ComHash, Commit, Config, DirHash, DirObject, FileHash,
+    FileObject, FileState, FilterDriver, Ignore, Index, Object, ObjectState, ReflogEntry,
+    Signature, StashEntry, Tag, TagHash, TagObject,
 };
 
 #[derive(Serialize, Deserialize)]
 pub struct Repo {
     pub remote: Option<String>,            // url of remote
     pub branches: HashMap<String, Branch>, // branch names to branch
+    #[serde(default)]
+    pub tags: HashMap<String, Tag>, // tag names to tag
     pub head: HeadState,                   // detached commit or a branch name
 
+    // settings from .mid/config.json
+    #[serde(skip)]
+    pub config: Config,
+
+    // directory containing .mid
+    // for bare repos the directory containing what would be in .mid
+    #[serde(skip)]
+    pub root: PathBuf,
+    // bare repos have no working tree
+    #[serde(skip)]
+    pub bare: bool,
+    // directory the user ran the command from
+    #[serde(skip)]
+    cwd: PathBuf,
+
     // lazily loaded store of objects
     #[serde(skip)]
     commits: UnsafeCell<HashMap<ComHash, Pin<Box<Commit>>>>,
@@ -27,9 +55,63 @@ pub struct Repo {
     files: UnsafeCell<HashMap<FileHash, Pin<Box<FileObject>>>>,
     #[serde(skip)]
     dirs: UnsafeCell<HashMap<DirHash, Pin<Box<DirObject>>>>,
+    #[serde(skip)]
+    tag_objs: UnsafeCell<HashMap<TagHash, Pin<Box<TagObject>>>>,
 
     // staging area
-    index: Option<DirHash>,
+    pub(super) index: Option<DirHash>,
+
+    // notes attached to commits
+    // tree of files named after the commit they annotate
+    #[serde(default)]
+    pub(super) notes: Option<DirHash>,
+
+    // stashed changes, newest last
+    #[serde(default)]
+    pub(super) stash: Vec<StashEntry>,
+
+    // ignore rules, loaded when first needed
+    #[serde(skip)]
+    ignore: Option<Ignore>,
+
+    // path attributes, loaded when first needed
+    // behind a refcell so files can be converted while writing the working tree
+    #[serde(skip)]
+    attributes: RefCell<Option<Attributes>>,
+
+    // stat data of tracked files from .mid/index.json
+    #[serde(skip)]
+    pub(super) index_file: Index,
+
+    // paths the filesystem monitor says changed since the last full status
+    // None if no monitor is running and everything has to be looked at
+    #[serde(skip)]
+    monitor_changes: Option<BTreeSet<PathBuf>>,
+
+    // ref movements not yet appended to .mid/logs
+    #[serde(skip)]
+    reflog: Vec<(String, ReflogEntry)>,
+
+    // worker pool for walking, hashing and writing files, built when first needed
+    #[serde(skip)]
+    pool: OnceCell<Arc<ThreadPool>>,
+}
+
+// resolve . and .. in a path without touching the file system
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+
+    for comp in path.components() {
+        match comp {
+            Component::ParentDir => {
+                normal.pop();
+            }
+            Component::CurDir => {}
+            comp => normal.push(comp),
+        }
+    }
+
+    normal
 }
 
 #[derive(Serialize, Deserialize)]
@@ -40,11 +122,92 @@ pub enum HeadState {
 }
 
 impl Repo {
-    // load repo from storage
+    // check if root is a repository
+    // returns whether it is bare, bare repos keep what would be in .mid directly in root
+    // a dir named .mid is always the storage of a work tree, never a bare repo
+    pub fn is_repo(root: &Path) -> Option<bool> {
+        if root.join(".mid/repo.json").is_file() {
+            Some(false)
+        } else if root.file_name() == Some(OsStr::new(".mid")) {
+            None
+        } else if root.join("repo.json").is_file() && root.join("objects").is_dir() {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    // find the root of the repository enclosing the current directory
+    // MID_DIR can be set to use a repository somewhere else
+    // it names the work tree or a bare repo, a .mid dir stands for the work tree it's in
+    pub fn discover() -> Result<PathBuf> {
+        if let Some(dir) = env::var_os("MID_DIR") {
+            let mut root = fs::canonicalize(&dir)
+                .with_context(|| format!("MID_DIR {} doesn't exist", Path::new(&dir).display()))?;
+
+            if root.file_name() == Some(OsStr::new(".mid")) {
+                root.pop();
+            }
+
+            if Self::is_repo(&root).is_none() {
+                bail!("MID_DIR {} isn't a repository", root.display());
+            }
+
+            return Ok(root);
+        }
+
+        let cwd = fs::canonicalize(env::current_dir()?)?;
+
+        for dir in cwd.ancestors() {
+            if Self::is_repo(dir).is_some() {
+                return Ok(dir.to_path_buf());
+            }
+        }
+
+        bail!("Not in a repository (or any parent directory)");
+    }
+
+    // load the repo enclosing the current directory
+    // user paths are relative to the current directory
     pub fn load() -> Result<Self> {
-        let file = File::open(".mid/repo.json")?;
+        let cwd = fs::canonicalize(env::current_dir()?)?;
 
-        let repo: Self = serde_json::from_reader(file)?;
+        let mut repo = Self::open(Self::discover()?)?;
+        repo.cwd = cwd;
+
+        Ok(repo)
+    }
+
+    // load repo rooted at root from storage
+    // doesn't depend on the current directory, user paths are relative to root
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let root = fs::canonicalize(root.as_ref())
+            .with_context(|| format!("{} doesn't exist", root.as_ref().display()))?;
+
+        let Some(bare) = Self::is_repo(&root) else {
+            bail!("{} isn't a repository", root.display());
+        };
+
+        let mid = if bare {
+            root.clone()
+        } else {
+            root.join(".mid")
+        };
+        let file = File::open(mid.join("repo.json"))?;
+
+        let mut repo: Self = serde_json::from_reader(file)?;
+        repo.cwd = root.clone();
+        repo.root = root;
+        repo.bare = bare;
+
+        if !repo.bare {
+            repo.index_file = Index::load(&repo.mid_path("index.json"))?;
+        }
+
+        if fs::exists(repo.mid_path("config.json"))? {
+            let file = File::open(repo.mid_path("config.json"))?;
+            repo.config = serde_json::from_reader(file)?;
+        }
 
         // validation
         if let HeadState::Branch(branch) = &repo.head {
@@ -56,18 +219,44 @@ impl Repo {
         Ok(repo)
     }
 
-    // initialize repository
+    // initialize repository in the current directory
     pub fn init() -> Result<Self> {
-        if fs::exists(".mid")? {
+        Self::init_at(env::current_dir()?)
+    }
+
+    // initialize repository at root, creating root if it doesn't exist
+    pub fn init_at(root: impl AsRef<Path>) -> Result<Self> {
+        Self::create(root.as_ref(), false)
+    }
+
+    // initialize repository without a working tree at root
+    pub fn init_bare_at(root: impl AsRef<Path>) -> Result<Self> {
+        Self::create(root.as_ref(), true)
+    }
+
+    fn create(root: &Path, bare: bool) -> Result<Self> {
+        fs::create_dir_all(root)?;
+        let root = fs::canonicalize(root)?;
+        let mid = if bare {
+            root.clone()
+        } else {
+            root.join(".mid")
+        };
+
+        if Self::is_repo(&root).is_some() || fs::exists(mid.join("objects"))? {
             bail!("Already in repository");
         }
+        if bare && root.file_name() == Some(OsStr::new(".mid")) {
+            bail!("A bare repository can't be named .mid");
+        }
 
         // creating dirs for storing objects
         let mut db = DirBuilder::new();
         db.recursive(true);
-        db.create(".mid/objects/commits")?;
-        db.create(".mid/objects/files")?;
-        db.create(".mid/objects/dirs")?;
+        db.create(mid.join("objects/commits"))?;
+        db.create(mid.join("objects/files"))?;
+        db.create(mid.join("objects/dirs"))?;
+        db.create(mid.join("objects/tags"))?;
 
         let main = Branch::new(ComHash([0; 20]));
         let mut branches = HashMap::new();
@@ -76,14 +265,81 @@ impl Repo {
         Ok(Self {
             remote: None,
             branches,
+            tags: HashMap::new(),
+            config: Config::default(),
+            cwd: root.clone(),
+            root,
+            bare,
             commits: UnsafeCell::new(HashMap::new()),
             files: UnsafeCell::new(HashMap::new()),
             dirs: UnsafeCell::new(HashMap::new()),
+            tag_objs: UnsafeCell::new(HashMap::new()),
             head: HeadState::Branch("main".to_string()),
             index: None,
+            notes: None,
+            stash: Vec::new(),
+            index_file: Index::default(),
+            ignore: None,
+            attributes: RefCell::new(None),
+            monitor_changes: None,
+            reflog: Vec::new(),
+            pool: OnceCell::new(),
         })
     }
 
+    // this repo's worker pool, sized by the threads setting
+    pub(super) fn pool(&self) -> Result<Arc<ThreadPool>> {
+        if self.pool.get().is_none() {
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(self.config.threads)
+                .build()?;
+            let _ = self.pool.set(Arc::new(pool));
+        }
+
+        Ok(self.pool.get().context("Worker pool wasn't built")?.clone())
+    }
+
+    // run parallel work on the worker pool
+    fn install<R: Send>(&self, work: impl FnOnce() -> R + Send) -> Result<R> {
+        Ok(self.pool()?.install(work))
+    }
+
+    // path of something stored in the .mid dir
+    pub fn mid_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        if self.bare {
+            self.root.join(path)
+        } else {
+            self.root.join(".mid").join(path)
+        }
+    }
+
+    // root of the working tree
+    // fails in bare repos
+    pub fn work_tree(&self) -> Result<PathBuf> {
+        if self.bare {
+            bail!("This operation must be run in a work tree, the repository is bare");
+        }
+
+        Ok(self.root.clone())
+    }
+
+    // convert a path given by the user, relative to the directory they ran the command from,
+    // into a path relative to the root of the repo
+    // the path doesn't need to exist
+    pub fn user_path(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let full = normalize_path(&self.cwd.join(path.as_ref()));
+
+        let rel = full
+            .strip_prefix(&self.root)
+            .with_context(|| format!("'{}' is outside the repository", path.as_ref().display()))?;
+
+        if rel.as_os_str().is_empty() {
+            Ok(PathBuf::from("."))
+        } else {
+            Ok(rel.to_path_buf())
+        }
+    }
+
     // get object from hashmap, load it from storage if it isn't there
     pub fn get_dir(&self, hash: DirHash) -> &DirObject {
         // SAFETY: access is unique because we never leak references to the hashmap
@@ -108,7 +364,16 @@ impl Repo {
         // SAFETY: references will stay valid because of pin
         unsafe { &mut *self.files.get() }
             .entry(hash)
-            .or_insert_with(|| Box::pin(self.file_from_hash(hash)))
+            // TODO: handle this error properly
+            .or_insert_with(|| {
+                Box::pin(
+                    self.file_from_hash(hash)
+                        .with_context(|| {
+                            format!("Failed loading file {}", hash.0.encode_hex::<String>())
+                        })
+                        .unwrap(),
+                )
+            })
     }
 
     // get object from hashmap, load it from storage if it isn't there
@@ -129,6 +394,24 @@ impl Repo {
             })
     }
 
+    // get object from hashmap, load it from storage if it isn't there
+    pub fn get_tag_obj(&self, hash: TagHash) -> &TagObject {
+        // SAFETY: access is unique because we never leak references to the hashmap
+        // SAFETY: references will stay valid because of pin
+        unsafe { &mut *self.tag_objs.get() }
+            .entry(hash)
+            // TODO: handle this error properly
+            .or_insert_with(|| {
+                Box::pin(
+                    self.tag_from_hash(hash)
+                        .with_context(|| {
+                            format!("Failed loading tag {}", hash.0.encode_hex::<String>())
+                        })
+                        .unwrap(),
+                )
+            })
+    }
+
     // get the current head commit
     pub fn get_head(&self) -> ComHash {
         match &self.head {
@@ -137,9 +420,24 @@ impl Repo {
         }
     }
 
+    // tree that would be committed
+    // the HEAD tree if nothing is staged
+    pub fn staged_tree(&self) -> DirHash {
+        self.index
+            .unwrap_or_else(|| self.get_commit(self.get_head()).objs)
+    }
+
+    // name of the current branch, or a placeholder if head is detached
+    pub fn head_name(&self) -> &str {
+        match &self.head {
+            HeadState::Branch(name) => name,
+            HeadState::Commit(_) => "(no branch)",
+        }
+    }
+
     // create new dir and store it in the repo
     // stored in the .mid dir at the end
-    fn new_dir(&mut self, objs: HashMap<OsString, Object>) -> DirHash {
+    pub(super) fn new_dir(&mut self, objs: HashMap<OsString, Object>) -> DirHash {
         let dir = DirObject {
             objs,
             state: ObjectState::New,
@@ -151,13 +449,8 @@ impl Repo {
 
     // create new commit and store it in the repo
     // stored in the .mid dir at the end
-    fn new_commit(&mut self, msg: String, prev: ComHash, objs: DirHash) -> ComHash {
-        let commit = Commit {
-            msg,
-            prev,
-            objs,
-            state: ObjectState::New,
-        };
+    pub(super) fn new_commit(&mut self, msg: String, prev: ComHash, objs: DirHash) -> ComHash {
+        let commit = Commit::new(msg, prev, objs, self.config.identity(), now());
         let hash = commit.hash();
         self.commits
             .get_mut()
@@ -166,6 +459,23 @@ impl Repo {
         hash
     }
 
+    // sign a commit that was created but not saved yet
+    fn sign_commit(&mut self, hash: ComHash, key: &SigningKey) {
+        if let Some(commit) = self.commits.get_mut().get_mut(&hash) {
+            commit.signature = Some(Signature::sign(key, &commit.payload()));
+        }
+    }
+
+    // key used for signing commits and tags
+    pub fn signing_key(&self) -> Result<SigningKey> {
+        let Some(path) = &self.config.signing_key else {
+            bail!("No signing key configured, set signing_key in .mid/config.json");
+        };
+
+        // relative paths are relative to the root of the repo
+        load_signing_key(self.root.join(path))
+    }
+
     // load object from the repo directory using its hash
     pub fn commit_from_hash(&self, hash: ComHash) -> Result<Commit> {
         // [0;20] represents hash of commit 0
@@ -174,13 +484,16 @@ impl Repo {
                 msg: "init".to_string(),
                 prev: ComHash([0; 20]),
                 objs: DirHash([0; 20]),
+                author: String::new(),
+                time: 0,
+                signature: None,
                 state: ObjectState::Existing,
             })
         } else {
-            let path = format!(
-                ".mid/objects/commits/{}.json",
+            let path = self.mid_path(format!(
+                "objects/commits/{}.json",
                 hash.0.encode_hex::<String>()
-            );
+            ));
 
             if !fs::exists(&path)? {
                 bail!("File doesn't exist");
@@ -192,6 +505,22 @@ impl Repo {
         }
     }
 
+    // load object from the repo directory using its hash
+    pub fn tag_from_hash(&self, hash: TagHash) -> Result<TagObject> {
+        let path = self.mid_path(format!(
+            "objects/tags/{}.json",
+            hash.0.encode_hex::<String>()
+        ));
+
+        if !fs::exists(&path)? {
+            bail!("File doesn't exist");
+        }
+
+        let file = File::open(path)?;
+
+        Ok(serde_json::from_reader(file)?)
+    }
+
     // load object from the repo directory using its hash
     pub fn dir_from_hash(&self, hash: DirHash) -> Result<DirObject> {
         if hash.0 == [0; 20] {
@@ -200,7 +529,10 @@ impl Repo {
                 state: ObjectState::Existing,
             })
         } else {
-            let path = format!(".mid/objects/dirs/{}.json", hash.0.encode_hex::<String>());
+            let path = self.mid_path(format!(
+                "objects/dirs/{}.json",
+                hash.0.encode_hex::<String>()
+            ));
 
             if !fs::exists(&path)? {
                 bail!("File doesn't exist");
@@ -213,149 +545,860 @@ impl Repo {
     }
 
     // load object from the repo directory using its hash
-    pub fn file_from_hash(&self, hash: FileHash) -> FileObject {
-        unimplemented!()
-    }
-
-    // stage entire folder or file
-    pub fn index_path(&mut self, path: impl AsRef<Path>) -> Result<Option<Object>> {
-        if !path.as_ref().try_exists()? {
-            Ok(None) // ts doesn't exist
-        } else if path.as_ref().is_dir() {
-            let mut objs = HashMap::new();
-            for entry in fs::read_dir(path.as_ref())? {
-                let entry = entry?;
-                if let Some(object) = self.index_path(entry.path())? {
-                    objs.insert(entry.file_name(), object);
+    pub fn file_from_hash(&self, hash: FileHash) -> Result<FileObject> {
+        let path = self.mid_path(format!(
+            "objects/files/{}/info.json",
+            hash.0.encode_hex::<String>()
+        ));
+
+        if !fs::exists(&path)? {
+            bail!("File doesn't exist");
+        }
+
+        let file = File::open(path)?;
+
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    // read the contents of a stored file
+    pub fn read_file(&self, hash: FileHash) -> Result<Vec<u8>> {
+        let path = self.mid_path(format!(
+            "objects/files/{}/FILE",
+            hash.0.encode_hex::<String>()
+        ));
+
+        if fs::exists(&path)? {
+            return Ok(fs::read(path)?);
+        }
+
+        // file was indexed but hasn't been saved yet
+        match &self.get_file(hash).state {
+            FileState::New(inpath) => return Ok(fs::read(inpath)?),
+            FileState::Data(data) => return Ok(data.clone()),
+            FileState::Existing => {}
+        }
+
+        bail!("File {} doesn't exist", hash.0.encode_hex::<String>())
+    }
+
+    // find the object at a path in a tree
+    pub fn tree_lookup(&self, tree: DirHash, path: &Path) -> Option<Object> {
+        let mut obj = Object::Dir(tree);
+
+        for comp in path.components() {
+            obj = *self.get_dir(obj.get_dir()?).objs.get(comp.as_os_str())?;
+        }
+
+        Some(obj)
+    }
+
+    // check if a path relative to the root is ignored
+    // ignore files are read when first needed
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> Result<bool> {
+        if self.ignore.is_none() {
+            let global = self.config.excludes_file();
+            self.ignore = Some(Ignore::new(
+                &self.root,
+                &self.mid_path("info/exclude"),
+                global.as_deref(),
+            )?);
+        }
+
+        self.ignore.as_mut().unwrap().is_ignored(path, is_dir)
+    }
+
+    // attributes of a path relative to the root
+    // attribute files are read when first needed
+    pub fn attributes(&self, path: &Path) -> Result<Attrs> {
+        let mut attributes = self.attributes.borrow_mut();
+        if attributes.is_none() {
+            *attributes = Some(Attributes::new(
+                &self.root,
+                &self.mid_path("info/attributes"),
+            )?);
+        }
+
+        attributes.as_mut().unwrap().get(path)
+    }
+
+    // ask the filesystem monitor what changed since the last full status
+    // returns the token to store once the caller has looked at every changed path
+    pub fn refresh_monitor(&mut self) -> Option<String> {
+        if self.bare {
+            return None;
+        }
+
+        let socket = monitor_socket(&self.mid_path(""));
+        let reply = query_monitor(&socket, self.index_file.monitor_token.as_deref());
+
+        self.monitor_changes = reply
+            .as_ref()
+            .and_then(|reply| reply.changed.clone())
+            .filter(|_| self.index_file.monitor_token.is_some())
+            .map(|changed| changed.into_iter().collect());
+
+        reply.map(|reply| reply.token)
+    }
+
+    // paths the monitor says changed, None if it can't tell
+    pub fn monitor_changes(&self) -> Option<&BTreeSet<PathBuf>> {
+        self.monitor_changes.as_ref()
+    }
+
+    // a tracked file the monitor says hasn't changed, without looking at it
+    fn monitored_file(&self, rel: &Path) -> Option<Object> {
+        let changes = self.monitor_changes.as_ref()?;
+
+        // a changed dir stands for everything in it
+        if rel.ancestors().any(|path| changes.contains(path)) {
+            return None;
+        }
+
+        let (hash, permissions) = self.index_file.known(rel)?;
+        Some(Object::File(hash, permissions))
+    }
+
+    // all files in a tree by their path, with their mode
+    pub fn flatten_tree(&self, tree: DirHash) -> BTreeMap<PathBuf, (FileHash, u32)> {
+        let mut files = BTreeMap::new();
+        let mut stack = vec![(PathBuf::new(), tree)];
+
+        while let Some((path, dir)) = stack.pop() {
+            for (name, obj) in &self.get_dir(dir).objs {
+                match obj {
+                    Object::File(hash, permissions) => {
+                        files.insert(path.join(name), (*hash, *permissions));
+                    }
+                    Object::Dir(hash) => stack.push((path.join(name), *hash)),
                 }
             }
+        }
 
-            Ok(Some(Object::Dir(self.new_dir(objs))))
-        } else {
-            // path is file
-            let hash = hash_file(path.as_ref())?;
-            let file = FileObject::new(path.as_ref());
-            self.files.get_mut().insert(hash, Box::pin(file));
-            Ok(Some(Object::File(hash)))
+        files
+    }
+
+    // update the working tree at path from the state of one tree to another
+    // only touches entries that differ between the two trees
+    pub fn write_tree(&self, from: DirHash, to: DirHash, path: &Path) -> Result<()> {
+        if from == to {
+            return Ok(());
+        }
+
+        let old = self.get_dir(from).objs.clone();
+        let new = self.get_dir(to).objs.clone();
+
+        // a tree from anywhere else could try to write outside the work tree or into .mid
+        for name in old.keys().chain(new.keys()) {
+            check_entry_name(name)?;
+            if path == self.root && name == ".mid" {
+                bail!("Trees can't have an entry named .mid at the root");
+            }
+        }
+
+        // remove entries the new tree doesn't have
+        for (name, obj) in &old {
+            if !new.contains_key(name) {
+                self.remove_tree(*obj, &path.join(name))?;
+            }
         }
+
+        for (name, obj) in new {
+            let path = path.join(&name);
+
+            match (old.get(&name), obj) {
+                (Some(a @ Object::File(..)), b @ Object::File(..)) if *a == b => {}
+                (Some(Object::Dir(a)), Object::Dir(b)) => self.write_tree(*a, b, &path)?,
+                (old, Object::Dir(b)) => {
+                    if let Some(old) = old {
+                        self.remove_tree(*old, &path)?;
+                    }
+                    fs::create_dir_all(&path)?;
+                    self.write_tree(DirHash([0; 20]), b, &path)?;
+                }
+                (old, Object::File(hash, permissions)) => {
+                    if let Some(old @ Object::Dir(_)) = old {
+                        self.remove_tree(*old, &path)?;
+                    }
+                    self.write_file(hash, permissions, &path)?;
+                }
+            }
+        }
+
+        Ok(())
     }
 
-    // add list of paths to index
-    // TODO: support wildcards
-    pub fn index_paths(&mut self, paths: Vec<impl AsRef<Path>>) -> Result<()> {
-        let canon_dot = fs::canonicalize(".")?;
+    // write the contents of a stored file to path in the working tree
+    // missing parent dirs are created and the mode is applied
+    pub fn write_file(&self, hash: FileHash, permissions: u32, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let rel = path.strip_prefix(&self.root)?;
+        fs::write(path, self.convert_to_work_tree(rel, self.read_file(hash)?)?)?;
+        set_permissions(path, permissions)
+    }
 
-        // canonicalize all paths relative to current directory
-        // fixes any bs
-        let paths = paths
+    // delete a file from the working tree along with any dirs it leaves empty
+    // path is relative to the root
+    pub fn delete_file(&self, path: &Path) -> Result<()> {
+        let root = self.work_tree()?;
+        let full = root.join(path);
+        if full.is_file() {
+            fs::remove_file(&full)?;
+        }
+
+        // stop at the root or the first dir that still has something in it
+        for dir in full.ancestors().skip(1) {
+            if dir == root || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // delete a tracked object from the working tree
+    // directories are only removed once they are empty so untracked files survive
+    pub fn remove_tree(&self, obj: Object, path: &Path) -> Result<()> {
+        match obj {
+            Object::File(..) => {
+                if path.is_file() {
+                    fs::remove_file(path)?;
+                }
+            }
+            Object::Dir(hash) => {
+                if !path.is_dir() {
+                    return Ok(());
+                }
+
+                for (name, obj) in self.get_dir(hash).objs.clone() {
+                    self.remove_tree(obj, &path.join(name))?;
+                }
+
+                // fails if untracked files are left
+                let _ = fs::remove_dir(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    // index the working tree versions of the files tracked in a dir
+    // untracked files are ignored, files missing from the working tree are dropped
+    // all the files are hashed in one batch on the worker pool
+    pub fn snapshot_dir(&mut self, dir: DirHash, path: &Path) -> Result<DirHash> {
+        let mut files = Vec::new();
+        let mut stack = vec![(path.to_path_buf(), dir)];
+
+        while let Some((dir_path, dir)) = stack.pop() {
+            for (name, obj) in &self.get_dir(dir).objs {
+                let path = dir_path.join(name);
+
+                // files the monitor says haven't changed aren't looked at
+                let known = match obj {
+                    Object::File(..) => self.monitored_file(path.strip_prefix(&self.root)?),
+                    Object::Dir(_) => None,
+                };
+
+                match obj {
+                    _ if known.is_some() => files.push(path),
+                    Object::Dir(hash) if path.is_dir() => stack.push((path, *hash)),
+                    _ if path.is_file() => files.push(path),
+                    _ => {}
+                }
+            }
+        }
+
+        let objs = self.hash_work_files(&files)?;
+        match self.nest_files(path, &files, objs, None)? {
+            Some(Object::Dir(hash)) => Ok(hash),
+            _ => Ok(self.new_dir(HashMap::new())),
+        }
+    }
+
+    // check that the index and working tree match HEAD
+    pub fn is_clean(&mut self) -> Result<bool> {
+        let root = self.work_tree()?;
+        let head_tree = self.get_commit(self.get_head()).objs;
+
+        if self.index.is_some_and(|index| index != head_tree) {
+            return Ok(false);
+        }
+
+        let work_tree = self.without_new_objects(|repo| repo.snapshot_dir(head_tree, &root))?;
+        Ok(work_tree == head_tree)
+    }
+
+    // run a scan of the working tree that only compares it
+    // files and dirs it creates are thrown away so they don't end up in the object store
+    pub(super) fn without_new_objects<R>(
+        &mut self,
+        scan: impl FnOnce(&mut Self) -> Result<R>,
+    ) -> Result<R> {
+        let files = self.files.get_mut().keys().copied().collect::<HashSet<_>>();
+        let dirs = self.dirs.get_mut().keys().copied().collect::<HashSet<_>>();
+
+        let out = scan(self);
+
+        // objects that were only loaded are read again when needed
+        self.files.get_mut().retain(|hash, _| files.contains(hash));
+        self.dirs.get_mut().retain(|hash, _| dirs.contains(hash));
+        out
+    }
+
+    // create new file from memory and store it in the repo
+    // stored in the .mid dir at the end
+    pub fn new_file(&mut self, data: Vec<u8>) -> FileHash {
+        let hash = hash_data(&data);
+        let file = FileObject {
+            state: FileState::Data(data),
+        };
+        self.files.get_mut().entry(hash).or_insert(Box::pin(file));
+        hash
+    }
+
+    // put hashed files into dir, replacing what was at their paths
+    // paths are relative to base, the hash of a dir doesn't depend on the order
+    fn nest_files(
+        &mut self,
+        base: &Path,
+        files: &[PathBuf],
+        objs: Vec<Object>,
+        dir: Option<DirHash>,
+    ) -> Result<Option<Object>> {
+        let edits = files
             .iter()
-            .map::<Result<_>, _>(|path| {
-                Ok(fs::canonicalize(path)?
-                    .strip_prefix(&canon_dot)?
-                    .to_path_buf())
-            })
+            .zip(objs)
+            .map(|(file, obj)| Ok((file.strip_prefix(base)?, Some(obj))))
             .collect::<Result<Vec<_>>>()?;
+        let edits = edits
+            .iter()
+            .map(|(file, obj)| (file.components(), *obj))
+            .collect();
 
-        // convert paths into vec of (pathbuf, components iterator)
-        let paths = paths
+        // empty dirs aren't tracked
+        self.edit_dir(dir, edits)
+    }
+
+    // a file in the working tree as it is stored
+    pub fn hash_work_file(&mut self, path: &Path) -> Result<Object> {
+        Ok(self.hash_work_files(&[path.to_path_buf()])?[0])
+    }
+
+    // files in the working tree as they are stored with their mode, in the same order as paths
+    // files that haven't changed since they were last hashed aren't hashed again
+    // the rest are read, converted and hashed on the worker pool
+    // converted files are kept in memory until they are stored
+    pub fn hash_work_files(&mut self, paths: &[PathBuf]) -> Result<Vec<Object>> {
+        // files the monitor says haven't changed aren't even looked at
+        let known = paths
             .iter()
-            .map(|pb| (pb, pb.components()))
-            .collect::<Vec<(_, _)>>();
-
-        self.index = Some(
-            step(
-                self,
-                Some(self.index.unwrap_or(self.get_commit(self.get_head()).objs)),
-                paths,
-            )?
-            .unwrap()
-            .get_dir()
-            .unwrap(),
-        );
+            .map(|path| Ok(self.monitored_file(path.strip_prefix(&self.root)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let metas = self.install(|| {
+            paths
+                .par_iter()
+                .zip(&known)
+                .map(|(path, known)| match known {
+                    Some(_) => Ok(None),
+                    None => fs::metadata(path).map(Some),
+                })
+                .collect::<io::Result<Vec<_>>>()
+        })??;
+
+        // attributes can't be shared between threads so conversions are worked out first
+        let mut hashed = vec![None; paths.len()];
+        let mut jobs = Vec::new();
+        for (i, (path, meta)) in paths.iter().zip(&metas).enumerate() {
+            let Some(meta) = meta else {
+                continue;
+            };
+            let rel = path.strip_prefix(&self.root)?;
+
+            match self.index_file.cached(rel, meta) {
+                Some(hash) => hashed[i] = Some((hash, None)),
+                None => {
+                    let attrs = self.attributes(rel)?;
+                    let clean = self.filter_command(&attrs, |driver| driver.clean.clone());
+                    jobs.push((i, CleanJob::new(path, rel, attrs, clean)));
+                }
+            }
+        }
 
-        // recursively iterates through paths staged to be commited
-        pub fn step(
-            repo: &mut Repo,
-            // hash of the object in HEAD representing this dir
-            // None if we are in a new dir
-            dirhash: Option<DirHash>,
-            // all the paths in one subpath and the state of their component iterators
-            paths: Vec<(&PathBuf, Components<'_>)>,
-        ) -> Result<Option<Object>> {
-            // maps paths off this directory to the paths param for the recursive call
-            let mut subdirs: HashMap<&OsStr, Vec<(&PathBuf, Components<'_>)>> = HashMap::new();
-
-            for mut path in paths.into_iter() {
-                if let Some(comp) = path.1.next() {
-                    subdirs
-                        .entry(comp.as_os_str())
-                        .or_default()
-                        .push((path.0, path.1));
-                } else {
-                    // we were at the end of the path for this
-                    return repo.index_path(path.0);
+        let root = self.root.clone();
+        let results = self.install(|| {
+            jobs.into_par_iter()
+                .map(|(i, job)| Ok((i, job.run(&root)?)))
+                .collect::<Result<Vec<_>>>()
+        })??;
+        for (i, result) in results {
+            hashed[i] = Some(result);
+        }
+
+        let mut objs = Vec::with_capacity(paths.len());
+        for (i, path) in paths.iter().enumerate() {
+            let (Some(meta), Some((hash, data))) = (&metas[i], hashed[i].take()) else {
+                objs.push(known[i].context("File wasn't hashed")?);
+                continue;
+            };
+
+            if let Entry::Vacant(entry) = self.files.get_mut().entry(hash) {
+                let state = match data {
+                    Some(data) => FileState::Data(data),
+                    None => FileState::New(path.clone()),
                 };
+                entry.insert(Box::pin(FileObject { state }));
             }
 
-            // if their is an existing dirobject then we keep the objects it contained
-            let mut objs = if let Some(hash) = dirhash {
-                repo.get_dir(hash).objs.clone()
+            self.index_file
+                .update(path.strip_prefix(&self.root)?, hash, meta);
+            objs.push(Object::File(hash, get_permissions(meta)));
+        }
+
+        Ok(objs)
+    }
+
+    // contents of a stored file as they should be in the working tree
+    // line endings are converted first, then the smudge filter runs
+    fn convert_to_work_tree(&self, rel: &Path, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        let attrs = self.attributes(rel)?;
+        if attrs.crlf() && attrs.is_text(&data) {
+            data = to_crlf(&data);
+        }
+
+        if let Some((command, required)) =
+            self.filter_command(&attrs, |driver| driver.smudge.clone())
+        {
+            match run_filter(&command, &self.root, rel, data.as_slice()) {
+                Ok(smudged) => return Ok(smudged),
+                Err(err) if required => return Err(err),
+                Err(_) => {}
+            }
+        }
+
+        Ok(data)
+    }
+
+    // one command of the filter driver selected by filter=<name>, and whether it is required
+    // drivers that aren't configured are skipped
+    fn filter_command(
+        &self,
+        attrs: &Attrs,
+        command: impl Fn(&FilterDriver) -> Option<String>,
+    ) -> Option<(String, bool)> {
+        let driver = self.config.filters.get(attrs.value("filter")?)?;
+        Some((command(driver)?, driver.required))
+    }
+
+    // set or remove the objects at paths in a tree without touching the working tree
+    // paths are relative to the root of the tree, dirs left empty are dropped
+    pub fn edit_tree(
+        &mut self,
+        tree: DirHash,
+        edits: Vec<(PathBuf, Option<Object>)>,
+    ) -> Result<DirHash> {
+        let edits = edits
+            .iter()
+            .map(|(path, obj)| (path.components(), *obj))
+            .collect::<Vec<_>>();
+
+        match self.edit_dir(Some(tree), edits)? {
+            Some(Object::Dir(hash)) => Ok(hash),
+            _ => Ok(self.new_dir(HashMap::new())),
+        }
+    }
+
+    pub(super) fn edit_dir(
+        &mut self,
+        dir: Option<DirHash>,
+        edits: Vec<(Components<'_>, Option<Object>)>,
+    ) -> Result<Option<Object>> {
+        let mut objs = dir
+            .map(|hash| self.get_dir(hash).objs.clone())
+            .unwrap_or_default();
+
+        // edits of entries in this dir are applied before edits inside its subdirs
+        let mut subdirs: HashMap<OsString, Vec<(Components<'_>, Option<Object>)>> = HashMap::new();
+        for (mut comps, obj) in edits {
+            let Some(name) = comps.next() else {
+                return Ok(obj); // edit of this dir itself
+            };
+            // .. and / components would put the entry outside the tree
+            check_entry_name(name.as_os_str())?;
+            let name = name.as_os_str().to_os_string();
+
+            if comps.clone().next().is_some() {
+                subdirs.entry(name).or_default().push((comps, obj));
+            } else if let Some(obj) = obj {
+                objs.insert(name, obj);
             } else {
-                HashMap::new()
+                objs.remove(&name);
+            }
+        }
+
+        for (name, edits) in subdirs {
+            let sub = objs.get(&name).and_then(Object::get_dir);
+            match self.edit_dir(sub, edits)? {
+                Some(obj) => objs.insert(name, obj),
+                None => objs.remove(&name),
             };
+        }
+
+        // empty dirs aren't tracked
+        if objs.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Object::Dir(self.new_dir(objs))))
+    }
 
-            // recursive call on all subpaths
-            // insert new objects into the objs
-            for (k, v) in subdirs {
-                if let Some(new) = step(repo, objs.get(k).and_then(Object::get_dir), v)? {
-                    objs.insert(k.to_os_string(), new);
-                } else {
-                    // no file at that path
-                    objs.remove(k); // remove entry if it was in dir but was deleted
+    // add list of paths to index
+    // paths are relative to the root of the repo
+    // paths that don't exist in the working tree are removed from the index
+    pub fn index_paths(&mut self, paths: Vec<impl AsRef<Path>>) -> Result<()> {
+        let root = self.work_tree()?;
+
+        // normalize all paths relative to the root
+        // fixes any bs
+        let paths = paths
+            .iter()
+            .map(|path| {
+                let normal = normalize_path(&root.join(path));
+                match normal.strip_prefix(&root) {
+                    Ok(rel) => Ok(rel.to_path_buf()),
+                    Err(_) => bail!("'{}' is outside the repository", path.as_ref().display()),
                 }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // explicitly named paths that are ignored can only be added if they are tracked
+        let staged = self.staged_tree();
+        for path in &paths {
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+
+            if path.starts_with(".mid") {
+                bail!(
+                    "Can't add '{}', it is inside the repository's storage",
+                    path.display()
+                );
+            }
+
+            if self.tree_lookup(staged, path).is_none()
+                && root.join(path).exists()
+                && self.is_ignored(path, root.join(path).is_dir())?
+            {
+                bail!("The path '{}' is ignored by .midignore", path.display());
+            }
+        }
+
+        // a named dir is replaced by what is in it now, and a missing path is removed
+        // everything left is hashed in one batch on the worker pool
+        let tracked = self.flatten_tree(staged);
+        let tracked_dirs = tracked_dirs(&tracked);
+        let mut dir = Some(staged);
+        let mut removed = Vec::new();
+        let mut files = BTreeSet::new();
+        for path in &paths {
+            let full = root.join(path);
+
+            if full.is_dir() {
+                let mut found = BTreeSet::new();
+                self.list_files(path, &tracked, &tracked_dirs, &mut found)?;
+                files.extend(found.into_iter().map(|file| root.join(file)));
+            } else if full.try_exists()? {
+                files.insert(full);
+            }
+
+            match path.as_os_str().is_empty() {
+                true => dir = None,
+                false => removed.push(path.components()),
             }
+        }
 
-            Ok(Some(Object::Dir(repo.new_dir(objs))))
+        // removals come first so they don't undo the new files
+        if !removed.is_empty() {
+            let edits = removed.into_iter().map(|comps| (comps, None)).collect();
+            dir = self.edit_dir(dir, edits)?.and_then(|obj| obj.get_dir());
         }
 
+        let files = files.into_iter().collect::<Vec<_>>();
+        let objs = self.hash_work_files(&files)?;
+        let tree = match self.nest_files(&root, &files, objs, dir)? {
+            Some(obj) => obj
+                .get_dir()
+                .context("Root of the repository isn't a directory")?,
+            None => self.new_dir(HashMap::new()), // nothing left to track
+        };
+        self.check_collisions(staged, tree)?;
+        self.index = Some(tree);
+
         Ok(())
     }
 
     // takes dirobj out of index and commits it
-    pub fn commit_index(&mut self, msg: String) -> Result<()> {
-        let Some(index) = self.index.take() else {
+    // signs the commit if sign is set or signing is configured
+    pub fn commit_index(&mut self, msg: String, sign: bool) -> Result<ComHash> {
+        let key = if sign || self.config.sign_commits {
+            Some(self.signing_key()?)
+        } else {
+            None
+        };
+
+        let Some(index) = self.index else {
             bail!("Index empty")
         };
+        self.check_collisions(self.get_commit(self.get_head()).objs, index)?;
+        self.index = None;
 
-        self.append_commit(msg, index);
+        let hash = self.append_commit(msg, index);
 
-        Ok(())
+        if let Some(key) = key {
+            self.sign_commit(hash, &key);
+        }
+
+        Ok(hash)
     }
 
     // appends commit to head, moves head to point at new commit
-    pub fn append_commit(&mut self, msg: String, new_dir: DirHash) {
+    pub fn append_commit(&mut self, msg: String, new_dir: DirHash) -> ComHash {
+        let reason = format!("commit: {}", msg.lines().next().unwrap_or(""));
         let new_head = self.new_commit(msg, self.get_head(), new_dir);
 
+        self.move_head(new_head, reason);
+
+        new_head
+    }
+
+    // moves the current branch, or the detached head, to point at a new commit
+    pub fn move_head(&mut self, new: ComHash, msg: String) {
+        let old = self.get_head();
+
         match &self.head {
             HeadState::Commit(comhash) => {
-                self.head = HeadState::Commit(new_head);
+                self.head = HeadState::Commit(new);
             }
             HeadState::Branch(branch) => {
-                *self.branches.get_mut(branch).unwrap() = Branch { head: new_head }
+                let branch = branch.clone();
+                *self.branches.get_mut(&branch).unwrap() = Branch { head: new };
+                self.log_ref(&branch, old, new, msg.clone());
             }
         }
+
+        self.log_ref("HEAD", old, new, msg);
+    }
+
+    // points HEAD at a different branch or commit
+    pub fn set_head(&mut self, head: HeadState, msg: String) {
+        let old = self.get_head();
+        self.head = head;
+        self.log_ref("HEAD", old, self.get_head(), msg);
+    }
+
+    // record a movement of a ref
+    // written to .mid/logs when the repo is saved
+    fn log_ref(&mut self, name: &str, old: ComHash, new: ComHash, msg: String) {
+        let entry = ReflogEntry {
+            old,
+            new,
+            who: self.config.identity(),
+            time: now(),
+            msg,
+        };
+        self.reflog.push((name.to_string(), entry));
+    }
+
+    // all movements of HEAD or a branch, oldest first
+    pub fn get_reflog(&self, name: &str) -> Result<Vec<ReflogEntry>> {
+        let mut entries = read_reflog(&self.mid_path("logs"), name)?;
+
+        entries.extend(
+            self.reflog
+                .iter()
+                .filter(|(n, _)| n == name)
+                .map(|(_, entry)| entry.clone()),
+        );
+
+        Ok(entries)
+    }
+
+    // commit a tag points to, following annotated tags to their target
+    pub fn peel_tag(&self, tag: Tag) -> ComHash {
+        match tag {
+            Tag::Lightweight(hash) => hash,
+            Tag::Annotated(hash) => self.get_tag_obj(hash).target,
+        }
+    }
+
+    // create a tag pointing at target
+    // tags with a message are stored as annotated tag objects, which can be signed
+    pub fn create_tag(
+        &mut self,
+        name: String,
+        target: ComHash,
+        msg: Option<String>,
+        sign: bool,
+    ) -> Result<()> {
+        if sign && msg.is_none() {
+            bail!("Only annotated tags can be signed");
+        }
+
+        // names that resolve couldn't tell apart from other revisions, or that look like options
+        if name.is_empty()
+            || name == "HEAD"
+            || name.starts_with('-')
+            || name.contains(char::is_whitespace)
+            || name.contains("@{")
+        {
+            bail!("'{}' isn't a valid tag name", name);
+        }
+
+        if self.branches.contains_key(&name) {
+            bail!("'{}' is already a branch", name);
+        }
+
+        if self.tags.contains_key(&name) {
+            bail!("Tag '{}' already exists", name);
+        }
+
+        if target.0 == [0; 20] {
+            bail!("Can't tag the initial commit");
+        }
+
+        let tag = match msg {
+            None => Tag::Lightweight(target),
+            Some(msg) => {
+                let mut obj =
+                    TagObject::new(name.clone(), target, self.config.identity(), now(), msg);
+
+                if sign || self.config.sign_commits {
+                    obj.signature = Some(Signature::sign(&self.signing_key()?, &obj.payload()));
+                }
+
+                let hash = obj.hash();
+                self.tag_objs.get_mut().entry(hash).or_insert(Box::pin(obj));
+                Tag::Annotated(hash)
+            }
+        };
+
+        self.tags.insert(name, tag);
+
+        Ok(())
+    }
+
+    // remove tag, annotated tag objects stay in the object store
+    pub fn delete_tag(&mut self, name: &str) -> Result<Tag> {
+        self.tags
+            .remove(name)
+            .with_context(|| format!("Tag '{}' not found", name))
+    }
+
+    // find the commit referred to by a revision
+    // accepts HEAD, branch names, tag names, reflog entries and (abbreviated) commit hashes
+    pub fn resolve(&self, rev: &str) -> Result<ComHash> {
+        if rev == "HEAD" {
+            return Ok(self.get_head());
+        }
+
+        // <ref>@{n} is the value the ref had n movements ago
+        if let Some((name, n)) = rev.strip_suffix('}').and_then(|rev| rev.rsplit_once("@{")) {
+            let name = if name.is_empty() { "HEAD" } else { name };
+            let n: usize = n
+                .parse()
+                .with_context(|| format!("Bad revision '{}'", rev))?;
+            let entries = self.get_reflog(name)?;
+
+            let Some(entry) = entries.len().checked_sub(n + 1).map(|i| &entries[i]) else {
+                bail!("Log for '{}' only has {} entries", name, entries.len());
+            };
+
+            return Ok(entry.new);
+        }
+
+        if let Some(branch) = self.branches.get(rev) {
+            return Ok(branch.head);
+        }
+
+        if let Some(tag) = self.tags.get(rev) {
+            return Ok(self.peel_tag(*tag));
+        }
+
+        if rev.len() < 4 || rev.len() > 40 || !rev.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("Unknown revision '{}'", rev);
+        }
+
+        let rev = rev.to_ascii_lowercase();
+
+        // commits in memory that haven't been saved yet
+        // SAFETY: we dont mutate the unsafecell while it is borrowed
+        let mut matches = unsafe { &*self.commits.get() }
+            .keys()
+            .map(|hash| hash.0.encode_hex::<String>())
+            .filter(|hex| hex.starts_with(&rev) && hex != &"0".repeat(40))
+            .collect::<Vec<_>>();
+
+        for entry in fs::read_dir(self.mid_path("objects/commits"))? {
+            let name = entry?.file_name();
+            let Some(hex) = name.to_str().and_then(|n| n.strip_suffix(".json")) else {
+                continue;
+            };
+
+            if hex.starts_with(&rev) && !matches.iter().any(|m| m == hex) {
+                matches.push(hex.to_string());
+            }
+        }
+
+        match matches.as_slice() {
+            [hex] => {
+                let mut hash = [0; 20];
+                hex::decode_to_slice(hex, &mut hash)?;
+                Ok(ComHash(hash))
+            }
+            [] => bail!("Unknown revision '{}'", rev),
+            _ => bail!("Ambiguous revision '{}'", rev),
+        }
     }
 
     // store any changes to the repo
-    pub fn save(&self) -> Result<()> {
-        let file = File::create(".mid/repo.json")?;
+    pub fn save(&mut self) -> Result<()> {
+        let file = File::create(self.mid_path("repo.json"))?;
         serde_json::to_writer_pretty(file, self)?;
 
+        // append ref movements to .mid/logs
+        for (name, entry) in std::mem::take(&mut self.reflog) {
+            append_reflog(&self.mid_path("logs"), &name, &entry)?;
+        }
+
+        self.save_index()?;
+        self.save_objects()
+    }
+
+    // only store the stat data of the working tree in .mid/index.json
+    // read only commands use this so the files they hashed don't end up in the object store
+    pub fn save_index(&mut self) -> Result<()> {
+        // bare repos have no working tree to keep stat data for
+        if !self.bare {
+            let staged = self.flatten_tree(self.staged_tree());
+            self.index_file.sync(&staged);
+            self.index_file.save(&self.mid_path("index.json"))?;
+        }
+
+        Ok(())
+    }
+
+    // store new objects in the .mid dir
+    // has to happen before the working tree is changed because new files are copied from it
+    pub fn save_objects(&self) -> Result<()> {
         // write commits to .mid dir
         // SAFETY: we dont mutate the unsafecell while it is borrowed
         for (key, value) in unsafe { &*self.commits.get() } {
             if let ObjectState::New = value.state {
-                let path = format!(".mid/objects/commits/{}.json", key.0.encode_hex::<String>());
+                let path = self.mid_path(format!(
+                    "objects/commits/{}.json",
+                    key.0.encode_hex::<String>()
+                ));
 
                 if fs::exists(&path)? {
                     continue;
@@ -371,7 +1414,10 @@ impl Repo {
         // SAFETY: we dont mutate the unsafecell while it is borrowed
         for (key, value) in unsafe { &*self.dirs.get() } {
             if let ObjectState::New = value.state {
-                let path = format!(".mid/objects/dirs/{}.json", key.0.encode_hex::<String>());
+                let path = self.mid_path(format!(
+                    "objects/dirs/{}.json",
+                    key.0.encode_hex::<String>()
+                ));
 
                 if fs::exists(&path)? {
                     continue;
@@ -383,29 +1429,65 @@ impl Repo {
             }
         }
 
-        // write files to .mid dir
+        // write tags to .mid dir
         // SAFETY: we dont mutate the unsafecell while it is borrowed
-        for (key, value) in unsafe { &*self.files.get() } {
-            if let FileState::New(inpath) = &value.state {
-                let path = format!(".mid/objects/files/{}", key.0.encode_hex::<String>());
+        for (key, value) in unsafe { &*self.tag_objs.get() } {
+            if let ObjectState::New = value.state {
+                fs::create_dir_all(self.mid_path("objects/tags"))?;
+
+                let path = self.mid_path(format!(
+                    "objects/tags/{}.json",
+                    key.0.encode_hex::<String>()
+                ));
 
                 if fs::exists(&path)? {
                     continue;
                 }
 
+                let tag_file = File::create(&path)?;
+
+                serde_json::to_writer_pretty(tag_file, &**value)?;
+            }
+        }
+
+        // write files to .mid dir
+        // blobs are copied on the worker pool
+        // SAFETY: we dont mutate the unsafecell while it is borrowed
+        let objects = self.mid_path("objects/files");
+        let files = unsafe { &*self.files.get() }
+            .iter()
+            .filter(|(_, value)| matches!(value.state, FileState::New(_) | FileState::Data(_)))
+            .collect::<Vec<_>>();
+
+        self.install(|| {
+            files.par_iter().try_for_each(|(key, value)| -> Result<()> {
+                let path = objects.join(key.0.encode_hex::<String>());
+
+                if fs::exists(&path)? {
+                    return Ok(());
+                }
+
                 let mut db = DirBuilder::new();
                 db.recursive(true);
 
                 db.create(&path)?;
 
-                let com_file = File::create(format!("{}/info.json", &path))?;
+                let com_file = File::create(path.join("info.json"))?;
 
-                serde_json::to_writer_pretty(com_file, &**value)?;
+                serde_json::to_writer_pretty(com_file, &***value)?;
 
                 // TODO: compression
-                fs::copy(inpath, format!("{}/FILE", &path))?;
-            }
-        }
+                match &value.state {
+                    FileState::New(inpath) => {
+                        fs::copy(inpath, path.join("FILE"))?;
+                    }
+                    FileState::Data(data) => fs::write(path.join("FILE"), data)?,
+                    FileState::Existing => {}
+                }
+
+                Ok(())
+            })
+        })??;
 
         Ok(())
     }
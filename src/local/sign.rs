@@ -0,0 +1,144 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use hex::ToHex;
+use serde::{Deserialize, Serialize};
+
+use super::{ComHash, Repo, Tag};
+
+// ed25519 signature over the payload of a commit or tag
+// stored next to the object, it isn't part of the object's hash
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Signature {
+    #[serde(with = "hex::serde")]
+    pub key: [u8; 32],
+    #[serde(with = "hex::serde")]
+    pub sig: [u8; 64],
+}
+
+impl Signature {
+    // sign payload with the secret key
+    pub fn sign(key: &SigningKey, payload: &[u8]) -> Self {
+        Self {
+            key: key.verifying_key().to_bytes(),
+            sig: key.sign(payload).to_bytes(),
+        }
+    }
+
+    // check that the signature was made over payload by its key
+    pub fn verify(&self, payload: &[u8]) -> Result<()> {
+        let key = VerifyingKey::from_bytes(&self.key).context("Bad public key")?;
+        let sig = ed25519_dalek::Signature::from_bytes(&self.sig);
+
+        key.verify_strict(payload, &sig)
+            .context("Signature doesn't match")
+    }
+
+    // public key as hex
+    pub fn key_hex(&self) -> String {
+        self.key.encode_hex()
+    }
+}
+
+// add one field to the bytes a signature covers
+// the length goes first so bytes can't move from one field into the next
+pub fn push_field(payload: &mut Vec<u8>, field: &[u8]) {
+    payload.extend((field.len() as u64).to_be_bytes());
+    payload.extend(field);
+}
+
+// read a secret key stored as hex
+pub fn load_signing_key(path: impl AsRef<Path>) -> Result<SigningKey> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed reading signing key {}", path.display()))?;
+
+    let mut seed = [0; 32];
+    hex::decode_to_slice(text.trim(), &mut seed).context("Signing key isn't 32 bytes of hex")?;
+
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+// create a new secret key and store it as hex
+// returns the public key
+pub fn generate_signing_key(path: impl AsRef<Path>) -> Result<VerifyingKey> {
+    if fs::exists(&path)? {
+        bail!("{} already exists", path.as_ref().display());
+    }
+
+    let mut seed = [0; 32];
+    File::open("/dev/urandom")?.read_exact(&mut seed)?;
+
+    let key = SigningKey::from_bytes(&seed);
+
+    // only readable by the owner
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(seed.encode_hex::<String>().as_bytes())?;
+
+    Ok(key.verifying_key())
+}
+
+impl Repo {
+    // check the signature of a commit
+    pub fn verify_commit(&self, hash: ComHash) -> Result<Signature> {
+        let commit = self.get_commit(hash);
+
+        let Some(signature) = commit.signature else {
+            bail!("Commit {} isn't signed", hash.0.encode_hex::<String>());
+        };
+
+        signature.verify(&commit.payload())?;
+        self.check_trusted(&signature)?;
+
+        Ok(signature)
+    }
+
+    // check the signature of an annotated tag
+    pub fn verify_tag(&self, name: &str) -> Result<Signature> {
+        let Some(tag) = self.tags.get(name) else {
+            bail!("Tag '{}' not found", name);
+        };
+
+        let Tag::Annotated(hash) = tag else {
+            bail!("Tag '{}' is lightweight and can't be signed", name);
+        };
+
+        let obj = self.get_tag_obj(*hash);
+
+        let Some(signature) = obj.signature else {
+            bail!("Tag '{}' isn't signed", name);
+        };
+
+        signature.verify(&obj.payload())?;
+        self.check_trusted(&signature)?;
+
+        Ok(signature)
+    }
+
+    // refuse keys that aren't listed in the config
+    // with no keys listed any key is accepted, but none of them is trusted
+    fn check_trusted(&self, signature: &Signature) -> Result<()> {
+        if !self.config.trusted_keys.is_empty() && !self.is_trusted(signature) {
+            bail!("Signed with untrusted key {}", signature.key_hex());
+        }
+
+        Ok(())
+    }
+
+    // check if the key of a signature is listed in the config
+    pub fn is_trusted(&self, signature: &Signature) -> bool {
+        let key = signature.key_hex();
+        self.config
+            .trusted_keys
+            .iter()
+            .any(|trusted| trusted.eq_ignore_ascii_case(&key))
+    }
+}
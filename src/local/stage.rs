@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+use super::{ComHash, Object, Repo};
+
+impl Repo {
+    // stop tracking files, deleting them from the working tree unless cached is set
+    // refuses to lose changes that aren't committed unless forced
+    pub fn remove_paths(&mut self, paths: &[PathBuf], cached: bool, force: bool) -> Result<()> {
+        let root = self.work_tree()?;
+        let head_tree = self.get_commit(self.get_head()).objs;
+        let staged_tree = self.staged_tree();
+
+        if !force {
+            for path in paths {
+                let staged = self.tree_lookup(staged_tree, path);
+                let head = self.tree_lookup(head_tree, path);
+                let work = match root.join(path).is_file() {
+                    true => Some(self.hash_work_file(&root.join(path))?),
+                    false => None,
+                };
+
+                // a missing file has nothing left to lose
+                let modified = work.is_some() && work != staged;
+
+                if modified && staged != head {
+                    bail!(
+                        "'{}' has staged content different from both the file and HEAD",
+                        path.display()
+                    );
+                }
+                if !cached && modified {
+                    bail!("'{}' has local modifications", path.display());
+                }
+                if !cached && staged != head {
+                    bail!("'{}' has changes staged in the index", path.display());
+                }
+            }
+        }
+
+        let edits = paths.iter().map(|path| (path.clone(), None)).collect();
+        self.index = Some(self.edit_tree(staged_tree, edits)?);
+
+        if !cached {
+            for path in paths {
+                self.delete_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // set the staged version of paths to their version in a commit
+    // paths the commit doesn't have are removed from the index
+    pub fn restore_staged(&mut self, paths: &[PathBuf], source: ComHash) -> Result<()> {
+        let tree = self.get_commit(source).objs;
+        let staged_tree = self.staged_tree();
+
+        let edits = paths
+            .iter()
+            .map(|path| (path.clone(), self.tree_lookup(tree, path)))
+            .collect();
+        self.index = Some(self.edit_tree(staged_tree, edits)?);
+
+        Ok(())
+    }
+
+    // overwrite paths in the working tree with their version in the index, or a commit
+    // paths that are tracked but missing from the source are deleted
+    pub fn restore_files(&mut self, paths: &[PathBuf], source: Option<ComHash>) -> Result<()> {
+        let root = self.work_tree()?;
+        let tree = match source {
+            Some(commit) => self.get_commit(commit).objs,
+            None => self.staged_tree(),
+        };
+
+        for path in paths {
+            let full = root.join(path);
+
+            match self.tree_lookup(tree, path) {
+                Some(Object::File(hash, permissions)) => {
+                    if full.is_dir() {
+                        fs::remove_dir_all(&full)?;
+                    }
+                    self.write_file(hash, permissions, &full)?;
+                }
+                _ => {
+                    if full.is_file() {
+                        fs::remove_file(&full)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // check if a path names a tracked dir rather than a file
+    pub fn is_tracked_dir(&self, path: &Path) -> bool {
+        // the root of the repo is always a dir
+        if path.as_os_str().is_empty() || path == Path::new(".") {
+            return true;
+        }
+
+        matches!(
+            self.tree_lookup(self.staged_tree(), path),
+            Some(Object::Dir(_))
+        )
+    }
+
+    // move a tracked file or dir in the working tree and the index
+    // the staged object is re-linked at its new path so nothing is rehashed
+    // moving onto an existing dir moves into it
+    pub fn move_path(&mut self, src: &Path, dst: &Path, force: bool) -> Result<PathBuf> {
+        let root = self.work_tree()?;
+        let staged_tree = self.staged_tree();
+
+        let Some(obj) = self
+            .tree_lookup(staged_tree, src)
+            .filter(|_| !src.as_os_str().is_empty())
+        else {
+            bail!("'{}' isn't tracked", src.display());
+        };
+
+        let mut dst = dst.to_path_buf();
+        if root.join(&dst).is_dir() {
+            dst = dst.join(src.file_name().unwrap_or_default());
+        }
+
+        if dst.starts_with(src) && dst != src {
+            bail!(
+                "Can't move '{}' to a subdirectory of itself, '{}'",
+                src.display(),
+                dst.display()
+            );
+        }
+        if !root.join(src).exists() {
+            bail!("'{}' doesn't exist in the working tree", src.display());
+        }
+        if !dst.parent().is_some_and(|dir| root.join(dir).is_dir()) {
+            bail!("Destination directory of '{}' doesn't exist", dst.display());
+        }
+        if root.join(&dst).exists() && (!force || root.join(&dst).is_dir()) {
+            bail!("Destination '{}' already exists", dst.display());
+        }
+
+        // stat data that still matches the files, carried over to the new paths
+        let moved = self
+            .index_file
+            .entries
+            .keys()
+            .filter(|path| path.starts_with(src))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut cached = Vec::new();
+        for path in moved {
+            let meta = fs::metadata(root.join(&path));
+            let hash = meta
+                .ok()
+                .and_then(|meta| self.index_file.cached(&path, &meta));
+            self.index_file.entries.remove(&path);
+            if let Some(hash) = hash {
+                let rel = path.strip_prefix(src)?;
+                let path = match rel.as_os_str().is_empty() {
+                    true => dst.clone(),
+                    false => dst.join(rel),
+                };
+                cached.push((path, hash));
+            }
+        }
+
+        fs::rename(root.join(src), root.join(&dst))?;
+
+        for (path, hash) in cached {
+            let meta = fs::metadata(root.join(&path))?;
+            self.index_file.update(&path, hash, &meta);
+        }
+
+        let edits = vec![(src.to_path_buf(), None), (dst.clone(), Some(obj))];
+        self.index = Some(self.edit_tree(staged_tree, edits)?);
+
+        Ok(dst)
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::{bail, Result};
+use hex::ToHex;
+use serde::{Deserialize, Serialize};
+
+use super::{now, ComHash, DirHash, Repo};
+
+// shelved changes
+// stored as two commits: the index on top of the commit that was checked out
+// and the working tree on top of the index
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct StashEntry {
+    pub commit: ComHash, // working tree commit
+    pub time: u64,
+}
+
+impl Repo {
+    // save the index and working tree changes, then reset them to HEAD
+    pub fn stash_push(&mut self, msg: Option<String>) -> Result<()> {
+        let base = self.get_head();
+        let head_tree = self.get_commit(base).objs;
+        let index_tree = self.staged_tree();
+        let root = self.work_tree()?;
+        let work_tree = self.snapshot_dir(index_tree, &root)?;
+
+        if index_tree == head_tree && work_tree == head_tree {
+            bail!("No local changes to save");
+        }
+
+        let summary = format!(
+            "{}: {} {}",
+            self.head_name(),
+            &base.0.encode_hex::<String>()[..7],
+            self.get_commit(base).msg.lines().next().unwrap_or("")
+        );
+        let msg = msg.unwrap_or_else(|| format!("WIP on {summary}"));
+
+        let index_commit = self.new_commit(format!("index on {summary}"), base, index_tree);
+        let work_commit = self.new_commit(msg, index_commit, work_tree);
+
+        self.stash.push(StashEntry {
+            commit: work_commit,
+            time: now(),
+        });
+
+        // working tree files are copied into the object store before they are overwritten
+        self.save_objects()?;
+        self.write_tree(work_tree, head_tree, &root)?;
+        self.index = None;
+
+        Ok(())
+    }
+
+    // stash entries, newest first
+    pub fn stash_list(&self) -> impl Iterator<Item = &StashEntry> {
+        self.stash.iter().rev()
+    }
+
+    // get stash@{n}
+    pub fn get_stash(&self, n: usize) -> Result<StashEntry> {
+        let Some(i) = self.stash.len().checked_sub(n + 1) else {
+            bail!("stash@{{{}}} doesn't exist", n);
+        };
+
+        Ok(self.stash[i])
+    }
+
+    // commit the stash was made on, and the trees of its index and working tree
+    pub fn stash_trees(&self, entry: StashEntry) -> (ComHash, DirHash, DirHash) {
+        let work = self.get_commit(entry.commit);
+        let index = self.get_commit(work.prev);
+        (index.prev, index.objs, work.objs)
+    }
+
+    // reapply the working tree changes of stash@{n} on top of HEAD
+    // also restores the index if restore_index is set
+    // refuses without changing anything if HEAD changed a file the stash changed too
+    pub fn stash_apply(&mut self, n: usize, restore_index: bool) -> Result<()> {
+        let root = self.work_tree()?;
+        let entry = self.get_stash(n)?;
+        let (base, index_tree, work_tree) = self.stash_trees(entry);
+        let base_tree = self.get_commit(base).objs;
+        let head_tree = self.get_commit(self.get_head()).objs;
+
+        if restore_index && base != self.get_head() {
+            bail!("Can't restore the index, HEAD has moved since the stash was made");
+        }
+
+        if !self.is_clean()? {
+            bail!("Local changes would be overwritten, commit or stash them first");
+        }
+
+        // take the stashed version of every file the stash changed
+        // as long as HEAD still has it as it was when the stash was made
+        let mut edits = Vec::new();
+        let mut conflicts = Vec::new();
+        for (_, path) in self.changed_files(base_tree, work_tree) {
+            let stashed = self.tree_lookup(work_tree, &path);
+            let head = self.tree_lookup(head_tree, &path);
+
+            if head == stashed {
+                continue;
+            }
+            if head != self.tree_lookup(base_tree, &path) {
+                conflicts.push(format!(
+                    "'{}' was changed since the stash was made",
+                    path.display()
+                ));
+            } else if head.is_none() && root.join(&path).exists() {
+                conflicts.push(format!("untracked '{}' is in the way", path.display()));
+            } else {
+                edits.push((path, stashed));
+            }
+        }
+
+        if !conflicts.is_empty() {
+            bail!(
+                "Can't apply stash@{{{n}}}, the stash is kept:\n\t{}",
+                conflicts.join("\n\t")
+            );
+        }
+
+        let new_tree = self.edit_tree(head_tree, edits)?;
+        self.write_tree(head_tree, new_tree, &root)?;
+
+        if restore_index {
+            self.index = Some(index_tree);
+        }
+
+        Ok(())
+    }
+
+    // remove stash@{n}
+    pub fn stash_drop(&mut self, n: usize) -> Result<StashEntry> {
+        let entry = self.get_stash(n)?;
+        self.stash.remove(self.stash.len() - n - 1);
+        Ok(entry)
+    }
+
+    // apply stash@{n} and remove it
+    pub fn stash_pop(&mut self, n: usize, restore_index: bool) -> Result<StashEntry> {
+        self.stash_apply(n, restore_index)?;
+        self.stash_drop(n)
+    }
+}
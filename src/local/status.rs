@@ -0,0 +1,192 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use super::{tracked_dirs, DirHash, FileHash, Repo};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+    TypeChanged, // a file replaced by a dir or the other way round
+}
+
+impl Change {
+    // letter used in short formats
+    pub fn letter(&self) -> char {
+        match self {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+            Change::TypeChanged => 'T',
+        }
+    }
+}
+
+// state of the index and working tree compared to HEAD
+pub struct Status {
+    pub staged: Vec<(Change, PathBuf)>,   // HEAD compared to the index
+    pub unstaged: Vec<(Change, PathBuf)>, // index compared to the working tree
+    pub untracked: Vec<PathBuf>,          // untracked dirs end in a slash
+}
+
+impl Status {
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty() && self.unstaged.is_empty() && self.untracked.is_empty()
+    }
+}
+
+// untracked files as status lists them
+// dirs without tracked files are listed instead of their contents
+fn collapse_untracked(files: &[PathBuf], tracked_dirs: &BTreeSet<PathBuf>) -> Vec<PathBuf> {
+    let mut untracked = files
+        .iter()
+        .map(|file| {
+            let mut dirs = file
+                .ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .collect::<Vec<_>>();
+            dirs.reverse();
+
+            match dirs.into_iter().find(|dir| !tracked_dirs.contains(*dir)) {
+                Some(dir) => dir.join(""),
+                None => file.clone(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    untracked.sort();
+    untracked.dedup();
+    untracked
+}
+
+impl Repo {
+    // changes to files between two trees
+    // a file replaced by a dir is deleted and the files in the dir are added
+    pub fn changed_files(&self, old: DirHash, new: DirHash) -> Vec<(Change, PathBuf)> {
+        self.diff_trees(old, new)
+            .into_iter()
+            .map(|entry| {
+                let change = match entry.change() {
+                    Change::TypeChanged
+                        if entry.old.is_some_and(|old| old.get_file().is_some()) =>
+                    {
+                        Change::Deleted
+                    }
+                    Change::TypeChanged => Change::Added,
+                    change => change,
+                };
+                (change, entry.path)
+            })
+            .collect()
+    }
+
+    // compare HEAD, the index and the working tree
+    // uses the filesystem monitor if one is running to skip unchanged files
+    // nothing hashed from the working tree is kept to be stored
+    pub fn status(&mut self) -> Result<Status> {
+        self.without_new_objects(Self::scan_status)
+    }
+
+    fn scan_status(&mut self) -> Result<Status> {
+        let root = self.work_tree()?;
+        let token = self.refresh_monitor();
+        let head_tree = self.get_commit(self.get_head()).objs;
+        let staged_tree = self.staged_tree();
+        let work_tree = self.snapshot_dir(staged_tree, &root)?;
+
+        let staged = self.changed_files(head_tree, staged_tree);
+        let unstaged = self.changed_files(staged_tree, work_tree);
+
+        let tracked = self.flatten_tree(staged_tree);
+        let tracked_dirs = tracked_dirs(&tracked);
+
+        let monitored = match token {
+            Some(_) => self.monitored_untracked(&root, &tracked, &tracked_dirs)?,
+            None => None,
+        };
+        let files = match monitored {
+            Some(files) => files,
+            None => {
+                let mut files = BTreeSet::new();
+                self.list_files(Path::new(""), &tracked, &tracked_dirs, &mut files)?;
+                files
+                    .into_iter()
+                    .filter(|path| !tracked.contains_key(path))
+                    .collect()
+            }
+        };
+        let untracked = collapse_untracked(&files, &tracked_dirs);
+
+        // with a monitor every untracked file is remembered for the next status
+        match token {
+            Some(token) => {
+                self.index_file.monitor_token = Some(token);
+                self.index_file.untracked = files;
+            }
+            None => {
+                self.index_file.monitor_token = None;
+                self.index_file.untracked.clear();
+            }
+        }
+
+        Ok(Status {
+            staged,
+            unstaged,
+            untracked,
+        })
+    }
+
+    // untracked files from the last full status, updated with what the monitor saw change
+    // None if the monitor can't tell or the ignore rules changed
+    pub(super) fn monitored_untracked(
+        &mut self,
+        root: &Path,
+        tracked: &BTreeMap<PathBuf, (FileHash, u32)>,
+        tracked_dirs: &BTreeSet<PathBuf>,
+    ) -> Result<Option<Vec<PathBuf>>> {
+        let Some(changes) = self.monitor_changes().cloned() else {
+            return Ok(None);
+        };
+
+        if changes
+            .iter()
+            .any(|path| path.file_name() == Some(".midignore".as_ref()))
+        {
+            return Ok(None);
+        }
+
+        let mut candidates = self
+            .index_file
+            .untracked
+            .iter()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        for path in changes {
+            // everything in a new or moved dir may be untracked
+            if root.join(&path).is_dir() {
+                if !self.is_ignored(&path, true)? {
+                    self.list_files(&path, tracked, tracked_dirs, &mut candidates)?;
+                }
+            } else {
+                candidates.insert(path);
+            }
+        }
+
+        let mut files = Vec::new();
+        for path in candidates {
+            if tracked.contains_key(&path) || !root.join(&path).is_file() {
+                continue;
+            }
+            if self.is_ignored(&path, false)? {
+                continue;
+            }
+            files.push(path);
+        }
+
+        Ok(Some(files))
+    }
+}
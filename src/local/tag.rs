@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use super::{push_field, ComHash, ObjectState, Signature};
+
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TagHash(#[serde(with = "hex::serde")] pub [u8; 20]);
+
+// a tag is either just a name for a commit
+// or a name for a tag object stored in .mid/objects/tags
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tag {
+    Lightweight(ComHash),
+    Annotated(TagHash),
+}
+
+// annotated tag
+#[derive(Serialize, Deserialize)]
+pub struct TagObject {
+    pub name: String,
+    pub target: ComHash,
+    pub tagger: String,
+    pub time: u64,
+    pub msg: String,
+    // not part of the hash, signs the payload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+    #[serde(skip)]
+    pub state: ObjectState,
+}
+
+impl TagObject {
+    pub fn new(name: String, target: ComHash, tagger: String, time: u64, msg: String) -> Self {
+        Self {
+            name,
+            target,
+            tagger,
+            time,
+            msg,
+            signature: None,
+            state: ObjectState::New,
+        }
+    }
+
+    // bytes covered by the signature
+    pub fn payload(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        push_field(&mut payload, b"tag");
+        push_field(&mut payload, self.name.as_bytes());
+        push_field(&mut payload, &self.target.0);
+        push_field(&mut payload, self.tagger.as_bytes());
+        push_field(&mut payload, &self.time.to_be_bytes());
+        push_field(&mut payload, self.msg.as_bytes());
+        payload
+    }
+
+    // hash object
+    pub fn hash(&self) -> TagHash {
+        TagHash(Sha1::digest(self.payload())[..].try_into().unwrap())
+    }
+}
@@ -0,0 +1,200 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+
+// current time in seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// format seconds since the unix epoch as a utc date
+// e.g. 2025-03-01 14:02:11
+pub fn format_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+// days since the unix epoch of a date
+// days from civil, see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_date(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// number of days in a month of a year
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// parse a time given on the command line into seconds since the unix epoch
+// accepts utc dates like 2025-03-01 or 2025-03-01 14:02[:11], @<seconds>
+// and relative times like "3 days ago" or 2.weeks.ago
+pub fn parse_time(time: &str) -> Result<u64> {
+    let time = time.trim();
+
+    if let Some(secs) = time.strip_prefix('@') {
+        return secs.parse().with_context(|| format!("Bad time '{time}'"));
+    }
+
+    let words = time
+        .split(|c: char| c == '.' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    if let [count, unit, "ago"] = words[..] {
+        let count = count
+            .parse::<u64>()
+            .with_context(|| format!("Bad time '{time}'"))?;
+        let unit = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 3600,
+            "day" => 86400,
+            "week" => 7 * 86400,
+            "month" => 30 * 86400,
+            "year" => 365 * 86400,
+            _ => bail!("Unknown time unit '{unit}'"),
+        };
+        let secs = count
+            .checked_mul(unit)
+            .with_context(|| format!("'{time}' is too long ago"))?;
+        return Ok(now().saturating_sub(secs));
+    }
+
+    let (date, clock) = time.split_once([' ', 'T']).unwrap_or((time, "00:00:00"));
+
+    let numbers = |s: &str, sep| {
+        s.split(sep)
+            .map(|n| n.parse::<i64>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+    };
+    let (Some(date), Some(clock)) = (numbers(date, '-'), numbers(clock, ':')) else {
+        bail!("Bad time '{time}', expected a date like 2025-03-01 14:02:11");
+    };
+
+    let (year, month, day) = match date[..] {
+        [year, month, day]
+            if (0..=9999).contains(&year)
+                && (1..=12).contains(&month)
+                && (1..=days_in_month(year, month)).contains(&day) =>
+        {
+            (year, month, day)
+        }
+        _ => bail!("Bad date '{time}', expected year-month-day"),
+    };
+    let (hour, minute, second) = match clock[..] {
+        [hour, minute] => (hour, minute, 0),
+        [hour, minute, second] => (hour, minute, second),
+        _ => bail!("Bad time of day in '{time}', expected hours:minutes[:seconds]"),
+    };
+    if !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..60).contains(&second) {
+        bail!("Bad time of day in '{time}'");
+    }
+
+    let secs = days_from_date(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    u64::try_from(secs).with_context(|| format!("'{time}' is before 1970"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses() {
+        let cases = [
+            ("@0", Some(0)),
+            ("@1700000000", Some(1700000000)),
+            ("@x", None),
+            ("1970-01-01", Some(0)),
+            ("2000-02-29", Some(951782400)),
+            ("2024-02-29 12:30", Some(1709209800)),
+            ("2025-03-01 14:02:11", Some(1740837731)),
+            ("2025-03-01T14:02:11", Some(1740837731)),
+            ("  2025-12-31 23:59:59 ", Some(1767225599)),
+            ("1969-12-31", None),
+            ("2023-02-29", None),
+            ("1900-02-29", None),
+            ("2026-02-31", None),
+            ("2026-04-31", None),
+            ("2026-13-01", None),
+            ("2026-00-10", None),
+            ("2026-01-00", None),
+            ("99999-01-01", None),
+            ("2026-01-01 24:00", None),
+            ("2026-01-01 25:99", None),
+            ("2026-01-01 12:60", None),
+            ("2026-01-01 12:00:60", None),
+            ("2026-01-01 12", None),
+            ("2026-01", None),
+            ("yesterday", None),
+            ("3 fortnights ago", None),
+            ("99999999999999999 years ago", None),
+        ];
+
+        for (time, expected) in cases {
+            assert_eq!(parse_time(time).ok(), expected, "{time}");
+        }
+    }
+
+    #[test]
+    fn relative() {
+        let before = now();
+        let parsed = parse_time("2 hours ago").unwrap();
+        assert!(before - 7200 <= parsed && parsed <= now() - 7200);
+
+        assert_eq!(
+            parse_time("1.week.ago").unwrap() / 10,
+            (now() - 604800) / 10
+        );
+        assert_eq!(parse_time("100000 years ago").unwrap(), 0);
+    }
+
+    #[test]
+    fn formats() {
+        let cases = [
+            (0, "1970-01-01 00:00:00"),
+            (951782400, "2000-02-29 00:00:00"),
+            (1740837731, "2025-03-01 14:02:11"),
+        ];
+
+        for (secs, expected) in cases {
+            assert_eq!(format_time(secs), expected);
+            assert_eq!(parse_time(expected).unwrap(), secs);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+// names in a dir and whether each is a dir, symlinks to dirs aren't followed
+// a dir removed before it could be read is empty
+fn read_entries(dir: &Path) -> io::Result<Vec<(OsString, bool)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut entries = entries
+        .map(|entry| {
+            let entry = entry?;
+            Ok((entry.file_name(), entry.file_type()?.is_dir()))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+// walk the working tree at root below start, which is relative to root
+// all dirs at the same depth are read at once, on the pool if there is one
+// visit gets every entry's path relative to root and whether it is a dir,
+// dirs it returns true for are walked into
+// the repo's storage is never visited
+pub fn walk_tree(
+    root: &Path,
+    start: &Path,
+    pool: Option<&ThreadPool>,
+    mut visit: impl FnMut(&Path, bool) -> Result<bool>,
+) -> Result<()> {
+    let mut level = vec![start.to_path_buf()];
+
+    while !level.is_empty() {
+        let read = |dir: &PathBuf| read_entries(&root.join(dir));
+        let listings = match pool {
+            Some(pool) => pool.install(|| level.par_iter().map(read).collect::<Vec<_>>()),
+            None => level.iter().map(read).collect(),
+        };
+
+        let mut next = Vec::new();
+        for (dir, listing) in level.iter().zip(listings) {
+            for (name, is_dir) in listing? {
+                let path = dir.join(name);
+                if path == Path::new(".mid") {
+                    continue;
+                }
+
+                if visit(&path, is_dir)? && is_dir {
+                    next.push(path);
+                }
+            }
+        }
+
+        level = next;
+    }
+
+    Ok(())
+}
@@ -4,57 +4,696 @@
 mod local;
 use local::*;
 
+use hex::ToHex;
 use std::env;
 
 fn main() {
-    let args = env::args().collect::<Vec<String>>();
+    let mut args = env::args().collect::<Vec<String>>();
 
-    let repo;
+    // -C <dir> runs as if mid was started in dir
+    while args.len() > 2 && args[1] == "-C" {
+        env::set_current_dir(&args[2]).expect("Failed to change directory");
+        args.drain(1..3);
+    }
+
+    let mut repo;
 
+    // no command shows the status
     if args.len() == 1 {
-        println!("Status");
-        let repo = local::Repo::load().expect("Failed to load repo");
-        println!(
-            "JSON :\n{}",
-            serde_json::to_string_pretty(&repo).expect("Failed to serialize repo")
-        );
-        return;
+        args.push("status".to_string());
     }
 
     let command = &args[1];
 
     if command == "init" {
-        repo = Repo::init().expect("Failed to initalize repo");
+        // [--bare] [<dir>]
+        let bare = args[2..].iter().any(|arg| arg == "--bare");
+        let dir = args[2..]
+            .iter()
+            .find(|arg| !arg.starts_with('-'))
+            .map_or(".", |s| s.as_str());
+
+        repo = if bare {
+            Repo::init_bare_at(dir)
+        } else {
+            Repo::init_at(dir)
+        }
+        .expect("Failed to initalize repo");
         repo.save().expect("Failed to save repo");
         return;
     }
 
+    if command == "keygen" {
+        // <path>
+        // set signing_key in .mid/config.json to use the key
+        let path = args.get(2).expect("Missing key path");
+        let key = generate_signing_key(path).unwrap();
+        println!("Public key {}", key.to_bytes().encode_hex::<String>());
+        return;
+    }
+
     let mut repo = local::Repo::load().expect("Failed to load repo");
 
     match command.as_str() {
+        "status" => {
+            // [-s --short] [<pathspec>]
+            let mut status = repo.status().unwrap();
+
+            let spec = Pathspec::parse(
+                &repo,
+                &args[2..]
+                    .iter()
+                    .filter(|arg| !arg.starts_with('-'))
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap();
+            if !spec.is_empty() {
+                status.staged.retain(|(_, path)| spec.matches(path));
+                status.unstaged.retain(|(_, path)| spec.matches(path));
+                status.untracked.retain(|path| spec.matches(path));
+            }
+
+            if args[2..].iter().any(|arg| arg == "-s" || arg == "--short") {
+                // staged and unstaged state of each path side by side
+                let mut paths = std::collections::BTreeMap::new();
+                for (change, path) in &status.staged {
+                    paths.entry(path).or_insert([' ', ' '])[0] = change.letter();
+                }
+                for (change, path) in &status.unstaged {
+                    paths.entry(path).or_insert([' ', ' '])[1] = change.letter();
+                }
+
+                for (path, [x, y]) in paths {
+                    println!("{x}{y} {}", path.display());
+                }
+                for path in &status.untracked {
+                    println!("?? {}", path.display());
+                }
+            } else {
+                match &repo.head {
+                    HeadState::Branch(name) => println!("On branch {name}"),
+                    HeadState::Commit(hash) => {
+                        println!("HEAD detached at {}", &hash.0.encode_hex::<String>()[..7])
+                    }
+                }
+
+                let describe = |change: &Change| match change {
+                    Change::Added => "new file:",
+                    Change::Modified => "modified:",
+                    Change::Deleted => "deleted: ",
+                    Change::TypeChanged => "typechange:",
+                };
+
+                if !status.staged.is_empty() {
+                    println!("\nChanges to be committed:");
+                    for (change, path) in &status.staged {
+                        println!("\t{} {}", describe(change), path.display());
+                    }
+                }
+                if !status.unstaged.is_empty() {
+                    println!("\nChanges not staged for commit:");
+                    for (change, path) in &status.unstaged {
+                        println!("\t{} {}", describe(change), path.display());
+                    }
+                }
+                if !status.untracked.is_empty() {
+                    println!("\nUntracked files:");
+                    for path in &status.untracked {
+                        println!("\t{}", path.display());
+                    }
+                }
+
+                if status.staged.is_empty() {
+                    if status.is_clean() {
+                        println!("\nnothing to commit, working tree clean");
+                    } else {
+                        println!("\nno changes added to commit");
+                    }
+                }
+            }
+
+            // nothing was changed, only the stat data of hashed files is kept
+            repo.save_index().expect("Failed to save index");
+            return;
+        }
+        "monitor" => {
+            // [--stop]
+            // watches the working tree so status and add don't have to scan all of it
+            let socket = monitor_socket(&repo.mid_path(""));
+            if args[2..].iter().any(|arg| arg == "--stop") {
+                stop_monitor(&socket).unwrap();
+                println!("Stopped monitor");
+            } else {
+                let root = repo.work_tree().unwrap();
+                println!("Monitoring {}", root.display());
+                run_monitor(&root, &socket).unwrap();
+            }
+            return;
+        }
         "diff" => {
-            println!("Difference of two commits");
+            // [--cached --staged] [--name-status] [-U<n> --unified=<n>]
+            // [<commit or branch name> [<commit or branch name>]] [-- <pathspec>]
+            // the working tree against the index, HEAD against the index with --cached
+            // the working tree or with --cached the index against one commit, or two commits
+            let cached = args[2..]
+                .iter()
+                .any(|arg| arg == "--cached" || arg == "--staged");
+            let name_status = args[2..].iter().any(|arg| arg == "--name-status");
+            let context = args[2..]
+                .iter()
+                .find_map(|arg| {
+                    arg.strip_prefix("-U")
+                        .or_else(|| arg.strip_prefix("--unified="))
+                })
+                .map_or(3, |n| n.parse().expect("Bad number of context lines"));
+            let split = args
+                .iter()
+                .position(|arg| arg == "--")
+                .unwrap_or(args.len());
+            let revs = args[2..split]
+                .iter()
+                .filter(|arg| !arg.starts_with('-'))
+                .map(|rev| repo.resolve(rev).unwrap())
+                .collect::<Vec<_>>();
+            let spec = Pathspec::parse(&repo, &args[(split + 1).min(args.len())..]).unwrap();
+
+            let staged_tree = repo.staged_tree();
+            let (old, new) = match (&revs[..], cached) {
+                ([], false) => {
+                    let root = repo.work_tree().unwrap();
+                    (staged_tree, repo.snapshot_dir(staged_tree, &root).unwrap())
+                }
+                ([], true) => (repo.get_commit(repo.get_head()).objs, staged_tree),
+                ([rev], false) => {
+                    let root = repo.work_tree().unwrap();
+                    let work_tree = repo.snapshot_dir(staged_tree, &root).unwrap();
+                    (repo.get_commit(*rev).objs, work_tree)
+                }
+                ([rev], true) => (repo.get_commit(*rev).objs, staged_tree),
+                ([old, new], _) => (repo.get_commit(*old).objs, repo.get_commit(*new).objs),
+                _ => panic!("Too many commits to compare"),
+            };
+
+            let mut out = std::io::stdout().lock();
+            for entry in repo.diff_trees(old, new) {
+                if !spec.is_empty() && !spec.matches(&entry.path) {
+                    continue;
+                }
+
+                if name_status {
+                    println!("{}\t{}", entry.change().letter(), entry.path.display());
+                } else {
+                    repo.write_file_diff(&mut out, &entry, context).unwrap();
+                }
+            }
+
+            // nothing was changed, only the stat data of hashed files is kept
+            repo.save_index().expect("Failed to save index");
+            return;
         }
         "add" => {
             println!("Indexing");
 
-            repo.index_paths(args[2..].into()).unwrap();
-            // <path>+
+            // --patch-file <diff>
+            if let Some(i) = args[2..].iter().position(|arg| arg == "--patch-file") {
+                let file = args.get(i + 3).expect("Missing patch file");
+                let patch = std::fs::read(file).expect("Failed to read patch file");
+                for path in repo.apply_to_index(&patch).unwrap() {
+                    println!("Staged changes to '{}'", path.display());
+                }
+                repo.save().expect("Failed to save changes to repository");
+                return;
+            }
+
+            // <pathspec>+
+            let spec = Pathspec::parse(&repo, &args[2..]).unwrap();
+            if spec.is_empty() {
+                panic!("Nothing specified, nothing added");
+            }
+
+            let tree = repo.staged_tree();
+            let paths = repo.expand_pathspec(&spec, tree).unwrap();
+            repo.index_paths(paths).unwrap();
+            warn_collisions(&repo, tree, repo.staged_tree());
+        }
+        "rm" => {
+            // [--cached] [-r] [-f --force] <pathspec>+
+            let cached = args[2..].iter().any(|arg| arg == "--cached");
+            let recursive = args[2..].iter().any(|arg| arg == "-r");
+            let force = args[2..].iter().any(|arg| arg == "-f" || arg == "--force");
+            let patterns = args[2..]
+                .iter()
+                .filter(|arg| !arg.starts_with('-'))
+                .collect::<Vec<_>>();
+
+            let spec = Pathspec::parse(&repo, &patterns).unwrap();
+            if spec.is_empty() {
+                panic!("Nothing specified, nothing removed");
+            }
+
+            // naming a dir removes everything in it, which has to be asked for
+            if !recursive {
+                for pattern in &patterns {
+                    let path = repo.user_path(pattern).unwrap();
+                    if !is_glob(pattern) && repo.is_tracked_dir(&path) {
+                        panic!("Not removing '{pattern}' recursively without -r");
+                    }
+                }
+            }
+
+            let tree = repo.staged_tree();
+            let paths = repo.tracked_matches(&spec, &[tree]).unwrap();
+            repo.remove_paths(&paths, cached, force).unwrap();
+            for path in paths {
+                println!("rm '{}'", path.display());
+            }
+        }
+        "mv" => {
+            // [-f --force] <source>+ <destination>
+            let force = args[2..].iter().any(|arg| arg == "-f" || arg == "--force");
+            let mut paths = args[2..]
+                .iter()
+                .filter(|arg| !arg.starts_with('-'))
+                .map(|arg| repo.user_path(arg).unwrap())
+                .collect::<Vec<_>>();
+
+            let dst = paths.pop().expect("Missing destination");
+            if paths.is_empty() {
+                panic!("Missing source");
+            }
+            if paths.len() > 1 && !repo.work_tree().unwrap().join(&dst).is_dir() {
+                panic!("Destination '{}' isn't a directory", dst.display());
+            }
+
+            for src in paths {
+                let moved = repo.move_path(&src, &dst, force).unwrap();
+                println!("Renamed '{}' to '{}'", src.display(), moved.display());
+            }
+        }
+        "restore" => {
+            // [-S --staged] [-W --worktree] [-s --source <commit or branch name>] <pathspec>+
+            let mut staged = false;
+            let mut worktree = false;
+            let mut source = None;
+            let mut patterns = Vec::new();
+
+            let mut iter = args[2..].iter();
+            while let Some(arg) = iter.next() {
+                match arg.as_str() {
+                    "-S" | "--staged" => staged = true,
+                    "-W" | "--worktree" => worktree = true,
+                    "-s" | "--source" => {
+                        let rev = iter.next().expect("Missing source commit");
+                        source = Some(repo.resolve(rev).unwrap());
+                    }
+                    _ => patterns.push(arg),
+                }
+            }
+
+            // only the working tree is restored by default
+            if !staged {
+                worktree = true;
+            }
+
+            let spec = Pathspec::parse(&repo, &patterns).unwrap();
+            if spec.is_empty() {
+                panic!("Nothing specified, nothing restored");
+            }
+
+            // the index is restored from HEAD, the working tree from the index
+            let head = repo.get_head();
+            let head_tree = repo.get_commit(head).objs;
+            let source_tree = source.map(|commit| repo.get_commit(commit).objs);
+            let mut trees = vec![repo.staged_tree()];
+            trees.extend(source_tree);
+            if staged {
+                trees.push(head_tree);
+            }
+            let paths = repo.tracked_matches(&spec, &trees).unwrap();
+
+            if staged {
+                repo.restore_staged(&paths, source.unwrap_or(head)).unwrap();
+            }
+            if worktree {
+                let source = if staged {
+                    Some(source.unwrap_or(head))
+                } else {
+                    source
+                };
+                repo.restore_files(&paths, source).unwrap();
+            }
         }
         "commit" => {
-            // <msg>
-            repo.commit_index(args[2].clone()).unwrap();
+            // [-S --sign] <msg>
+            let sign = args[2..].iter().any(|arg| arg == "-S" || arg == "--sign");
+            let msg = args[2..]
+                .iter()
+                .find(|arg| *arg != "-S" && *arg != "--sign")
+                .expect("Missing commit message");
+            let hash = repo.commit_index(msg.clone(), sign).unwrap();
+            warn_collisions(
+                &repo,
+                repo.get_commit(repo.get_commit(hash).prev).objs,
+                repo.get_commit(hash).objs,
+            );
             println!("Commiting changes");
         }
+        "checkout" => {
+            // [-f --force] <commit or branch name>
+            let force = args[2..].iter().any(|arg| arg == "-f" || arg == "--force");
+            let rev = args[2..]
+                .iter()
+                .find(|arg| !arg.starts_with('-'))
+                .expect("Missing commit or branch name");
+
+            checkout(&mut repo, rev, force);
+        }
+        "reset" => {
+            // [--soft | --mixed | --hard] [<commit or branch name>]
+            let mode = if args[2..].iter().any(|arg| arg == "--soft") {
+                ResetMode::Soft
+            } else if args[2..].iter().any(|arg| arg == "--hard") {
+                ResetMode::Hard
+            } else {
+                ResetMode::Mixed
+            };
+            let rev = args[2..]
+                .iter()
+                .find(|arg| !arg.starts_with('-'))
+                .map_or("HEAD", |s| s.as_str());
+
+            let commit = repo.resolve(rev).unwrap();
+            repo.reset(commit, mode, rev).unwrap();
+            println!("HEAD is now at {}", &commit.0.encode_hex::<String>()[..7]);
+        }
         "branch" => {
             println!("Branching");
             // -c --checkout <commit or branch name>
+            if let Some(i) = args[2..]
+                .iter()
+                .position(|arg| arg == "-c" || arg == "--checkout")
+            {
+                let rev = args.get(i + 3).expect("Missing commit or branch name");
+                let force = args[2..].iter().any(|arg| arg == "-f" || arg == "--force");
+                checkout(&mut repo, rev, force);
+            }
             //    --reset <commit or branch name>
+            if let Some(i) = args[2..].iter().position(|arg| arg == "--reset") {
+                let rev = args.get(i + 3).expect("Missing commit or branch name");
+                let commit = repo.resolve(rev).unwrap();
+                repo.reset(commit, ResetMode::Mixed, rev).unwrap();
+            }
             //    --restore <commit or branch name>
             // -n --new <name>
             //    --rename <name>
             // -d --delete <name>
         }
+        "tag" => {
+            // list tags
+            // <name> [<commit or branch name>]
+            // -a --annotate <name> -m <msg> [<commit or branch name>]
+            // -s --sign <name> -m <msg> [<commit or branch name>]
+            // -d --delete <name>
+            match args.get(2).map(String::as_str) {
+                None | Some("-l") | Some("--list") => {
+                    let mut names = repo.tags.keys().collect::<Vec<_>>();
+                    names.sort();
+                    for name in names {
+                        println!("{name}");
+                    }
+                }
+                Some("-d") | Some("--delete") => {
+                    let name = args.get(3).expect("Missing tag name");
+                    repo.delete_tag(name).unwrap();
+                    println!("Deleted tag '{name}'");
+                }
+                Some(_) => {
+                    let mut msg = None;
+                    let mut sign = false;
+                    let mut rest = Vec::new();
+                    let mut iter = args[2..].iter();
+                    while let Some(arg) = iter.next() {
+                        match arg.as_str() {
+                            "-a" | "--annotate" => {
+                                msg.get_or_insert_with(String::new);
+                            }
+                            "-s" | "--sign" => {
+                                msg.get_or_insert_with(String::new);
+                                sign = true;
+                            }
+                            "-m" | "--message" => {
+                                msg = Some(iter.next().expect("Missing tag message").clone());
+                            }
+                            _ => rest.push(arg),
+                        }
+                    }
+
+                    let name = rest.first().expect("Missing tag name");
+                    let target = repo
+                        .resolve(rest.get(1).map_or("HEAD", |s| s.as_str()))
+                        .unwrap();
+                    repo.create_tag(name.to_string(), target, msg, sign)
+                        .unwrap();
+                }
+            }
+        }
+        "stash" => {
+            // [push] [-m <msg>]
+            // list
+            // show [<n>]
+            // apply [<n>] [--index]
+            // pop [<n>] [--index]
+            // drop [<n>]
+            let sub = args.get(2).map_or("push", |s| s.as_str());
+            let restore_index = args[2..].iter().any(|arg| arg == "--index");
+
+            // stash@{n} or n, defaults to the newest entry
+            let n = || {
+                args[2..]
+                    .iter()
+                    .skip(1)
+                    .find(|arg| !arg.starts_with('-'))
+                    .map_or(0, |arg| {
+                        arg.trim_start_matches("stash@{")
+                            .trim_end_matches('}')
+                            .parse::<usize>()
+                            .expect("Bad stash entry")
+                    })
+            };
+
+            match sub {
+                "push" | "-m" | "--message" => {
+                    let msg = args
+                        .iter()
+                        .position(|arg| arg == "-m" || arg == "--message")
+                        .map(|i| args.get(i + 1).expect("Missing stash message").clone());
+                    repo.stash_push(msg).unwrap();
+                    println!("Saved working directory and index state");
+                }
+                "list" => {
+                    for (i, entry) in repo.stash_list().enumerate() {
+                        println!(
+                            "stash@{{{i}}}: {} ({})",
+                            repo.get_commit(entry.commit).msg,
+                            format_time(entry.time)
+                        );
+                    }
+                }
+                "show" => {
+                    let (base, _, work_tree) = repo.stash_trees(repo.get_stash(n()).unwrap());
+
+                    for (change, path) in repo.changed_files(repo.get_commit(base).objs, work_tree)
+                    {
+                        println!("{} {}", change.letter(), path.display());
+                    }
+                }
+                "apply" => {
+                    repo.stash_apply(n(), restore_index).unwrap();
+                }
+                "pop" => {
+                    let n = n();
+                    repo.stash_pop(n, restore_index).unwrap();
+                    println!("Dropped stash@{{{n}}}");
+                }
+                "drop" => {
+                    let n = n();
+                    repo.stash_drop(n).unwrap();
+                    println!("Dropped stash@{{{n}}}");
+                }
+                other => panic!("Unknown stash command '{other}'"),
+            }
+        }
+        "notes" => {
+            // add [-f] -m <msg> [<commit or branch name>]
+            // show [<commit or branch name>]
+            // remove [<commit or branch name>]
+            let sub = args.get(2).expect("Missing notes command");
+
+            let mut msg = None;
+            let mut force = false;
+            let mut rev = "HEAD";
+            let mut iter = args[3..].iter();
+            while let Some(arg) = iter.next() {
+                match arg.as_str() {
+                    "-f" | "--force" => force = true,
+                    "-m" | "--message" => {
+                        msg = Some(iter.next().expect("Missing note message").clone());
+                    }
+                    other => rev = other,
+                }
+            }
+            let commit = repo.resolve(rev).unwrap();
+
+            match sub.as_str() {
+                "add" => {
+                    repo.add_note(commit, msg.expect("Missing note message"), force)
+                        .unwrap();
+                }
+                "show" => match repo.get_note(commit).unwrap() {
+                    Some(note) => println!("{note}"),
+                    None => panic!("No note found for '{rev}'"),
+                },
+                "remove" => {
+                    repo.remove_note(commit).unwrap();
+                    println!("Removed note for '{rev}'");
+                }
+                other => panic!("Unknown notes command '{other}'"),
+            }
+        }
+        "log" => {
+            // [-n <count>] [--oneline] [--format <format>] [--reverse]
+            // [--since <time>] [--until <time>] [--author <name>]
+            // [--show-signature] [<commit or branch name>]*
+            let mut filter = LogFilter::default();
+            let mut format = None;
+            let mut show_signature = false;
+            let mut revs = Vec::new();
+
+            let mut iter = args[2..].iter();
+            while let Some(arg) = iter.next() {
+                // --option=value is the same as --option value
+                let (arg, mut value) = match arg.split_once('=') {
+                    Some((arg, value)) if arg.starts_with("--") => (arg, Some(value.to_string())),
+                    _ => (arg.as_str(), None),
+                };
+                let mut value = |what| {
+                    value
+                        .take()
+                        .or_else(|| iter.next().cloned())
+                        .unwrap_or_else(|| panic!("Missing {what}"))
+                };
+
+                match arg {
+                    "-n" | "--max-count" => {
+                        filter.limit =
+                            Some(value("commit count").parse().expect("Bad commit count"))
+                    }
+                    "--oneline" => format = Some("%h %s".to_string()),
+                    "--format" | "--pretty" => format = Some(value("format")),
+                    "--reverse" => filter.reverse = true,
+                    "--since" | "--after" => {
+                        filter.since = Some(parse_time(&value("time")).unwrap())
+                    }
+                    "--until" | "--before" => {
+                        filter.until = Some(parse_time(&value("time")).unwrap())
+                    }
+                    "--author" => filter.author = Some(value("author")),
+                    "--show-signature" => show_signature = true,
+                    // -<count> or -n<count>
+                    _ if arg
+                        .strip_prefix("-n")
+                        .or_else(|| arg.strip_prefix('-'))
+                        .is_some_and(|n| {
+                            !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit())
+                        }) =>
+                    {
+                        let n = arg.trim_start_matches("-n").trim_start_matches('-');
+                        filter.limit = Some(n.parse().unwrap())
+                    }
+                    _ if arg.starts_with('-') => panic!("Unknown log option '{arg}'"),
+                    _ => revs.push(repo.resolve(arg).unwrap()),
+                }
+            }
+
+            if revs.is_empty() {
+                revs.push(repo.get_head());
+            }
+
+            for commit in repo.log(&revs, &filter) {
+                if let Some(format) = &format {
+                    println!("{}", repo.format_commit(commit, format).unwrap());
+                    continue;
+                }
+
+                let com = repo.get_commit(commit);
+
+                println!("commit {}", commit.0.encode_hex::<String>());
+                if show_signature && com.signature.is_some() {
+                    match repo.verify_commit(commit) {
+                        Ok(sig) => println!("{}", describe_signature(&repo, &sig)),
+                        Err(err) => println!("Bad signature: {err}"),
+                    }
+                }
+                // commits from before authors were recorded have neither
+                if com.time != 0 {
+                    println!("Author: {}", com.author);
+                    println!("Date:   {}", format_time(com.time));
+                }
+                println!();
+                for line in com.msg.lines() {
+                    println!("    {line}");
+                }
+
+                if let Some(note) = repo.get_note(commit).unwrap() {
+                    println!("\nNotes:");
+                    for line in note.lines() {
+                        println!("    {line}");
+                    }
+                }
+                println!();
+            }
+        }
+        "verify-commit" => {
+            // <commit or branch name>+
+            for rev in &args[2..] {
+                let sig = repo.verify_commit(repo.resolve(rev).unwrap()).unwrap();
+                println!("{rev}: {}", describe_signature(&repo, &sig));
+            }
+        }
+        "verify-tag" => {
+            // <tag name>+
+            for name in &args[2..] {
+                let sig = repo.verify_tag(name).unwrap();
+                println!("{name}: {}", describe_signature(&repo, &sig));
+            }
+        }
+        "reflog" => {
+            // [<branch name or HEAD>]
+            let name = args.get(2).map_or("HEAD", |s| s.as_str());
+
+            for (i, entry) in repo.get_reflog(name).unwrap().iter().rev().enumerate() {
+                println!(
+                    "{} {name}@{{{i}}}: {} ({}, {})",
+                    &entry.new.0.encode_hex::<String>()[..7],
+                    entry.msg,
+                    entry.who,
+                    format_time(entry.time)
+                );
+            }
+        }
+        "fsck" => {
+            // report names in any commit that collide on case insensitive filesystems
+            let collisions = repo.fsck();
+            for (commit, collision) in &collisions {
+                println!(
+                    "{}: names only differ by case or normalization: {collision}",
+                    &commit.0.encode_hex::<String>()[..7]
+                );
+            }
+            if collisions.is_empty() {
+                println!("No problems found");
+            }
+        }
         "remote" => {
             // change or update remote repository
             println!("Updating remote");
@@ -86,3 +725,40 @@ fn main() {
 
     repo.save().expect("Failed to save changes to repository");
 }
+
+// check out a branch, or detach HEAD at any other commit
+fn checkout(repo: &mut Repo, rev: &str, force: bool) {
+    let target = if repo.branches.contains_key(rev) {
+        HeadState::Branch(rev.to_string())
+    } else {
+        HeadState::Commit(repo.resolve(rev).unwrap())
+    };
+
+    repo.checkout(target, force).unwrap();
+    match &repo.head {
+        HeadState::Branch(name) => println!("Switched to branch '{name}'"),
+        HeadState::Commit(hash) => {
+            println!("HEAD is now at {}", &hash.0.encode_hex::<String>()[..7])
+        }
+    }
+}
+
+// a signature is only good if its key is trusted
+// otherwise it just shows the contents weren't changed since that key signed them
+fn describe_signature(repo: &Repo, sig: &Signature) -> String {
+    match repo.is_trusted(sig) {
+        true => format!("Good signature from {}", sig.key_hex()),
+        false => format!("Signature from untrusted key {}", sig.key_hex()),
+    }
+}
+
+// warn about names added between two trees that collide on case insensitive filesystems
+fn warn_collisions(repo: &Repo, old: DirHash, new: DirHash) {
+    if repo.config.name_collisions != CollisionCheck::Warn {
+        return;
+    }
+
+    for collision in repo.collisions(Some(old), new) {
+        eprintln!("warning: names only differ by case or normalization: {collision}");
+    }
+}